@@ -1,32 +1,146 @@
+use crate::diff_chunks::DiffChunks;
 use anyhow::Result;
+use futures::future::try_join_all;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use shared::services::chat_gpt;
+use shared::{
+    services::llm::{self, Llm, Provider},
+    utils::{code_index::CodeIndex, config, diff, prompt::Prompt},
+};
+
+const DEFAULT_TOKEN_BUDGET: usize = 60_000;
+const DEFAULT_CONTEXT_TOKENS: usize = 8_000;
+const DEFAULT_MAX_CHUNKS: usize = 8;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ReleaseSummary {
     pub items: Vec<SummaryCategory>,
+    /// Files whose diff was cut short to fit the token budget.
+    #[serde(default)]
+    pub truncated_files: Vec<String>,
+    /// Files left out of the summary entirely to fit the token budget.
+    #[serde(default)]
+    pub dropped_files: Vec<String>,
 }
 
 impl ReleaseSummary {
-    pub async fn new(diff: &str, commit_messages: &[String]) -> Result<Self> {
+    pub async fn new(
+        diff: &str,
+        commit_messages: &[String],
+        categories: &[String],
+        code_index: Option<&CodeIndex>,
+    ) -> Result<Self> {
         tracing::info!("Generating release summary");
 
         let commit_messages = commit_messages.join("\n");
+        let categories = categories.join("\n");
 
-        let user_prompt = format!(
-            "<Diff>{diff}</Diff>
-             <CommitMessages>{commit_messages}</CommitMessages>"
-        );
+        let token_budget = get_positive_config_number("SUMMARY_TOKEN_BUDGET", DEFAULT_TOKEN_BUDGET);
+        let max_chunks = get_positive_config_number("SUMMARY_MAX_CHUNKS", DEFAULT_MAX_CHUNKS);
+        let context_budget = match code_index {
+            Some(_) => config::get_number("SUMMARY_CONTEXT_TOKENS", DEFAULT_CONTEXT_TOKENS),
+            None => 0,
+        };
+
+        // Leave room for the commit messages and symbol context sent alongside every chunk
+        let diff_budget = token_budget
+            .saturating_sub(diff::estimate_tokens(&commit_messages) + context_budget)
+            .max(token_budget / 2);
+
+        let get_context = |diff: &str| code_index?.get_context(diff, context_budget);
+
+        let DiffChunks {
+            chunks,
+            truncated_files,
+            dropped_files,
+        } = DiffChunks::new(diff, diff_budget, max_chunks);
+
+        let mut summary = match chunks.as_slice() {
+            [] => anyhow::bail!("Diff has no files to summarise"),
+            [chunk] => {
+                Self::summarise(chunk, get_context(chunk), &commit_messages, &categories).await?
+            }
+            chunks => {
+                tracing::info!(
+                    "Diff exceeds token budget, summarising in {} chunks",
+                    chunks.len()
+                );
+
+                let partial_summaries = try_join_all(chunks.iter().map(|chunk| {
+                    Self::summarise(chunk, get_context(chunk), &commit_messages, &categories)
+                }))
+                .await?;
+
+                Self::merge(&partial_summaries, &commit_messages, &categories).await?
+            }
+        };
+
+        summary.truncated_files = truncated_files;
+        summary.dropped_files = dropped_files;
+
+        Ok(summary)
+    }
 
-        chat_gpt::Request {
-            user_prompt,
-            system_prompt: SYSTEM_PROMPT,
-            response_schema: response_schema(),
-            ..Default::default()
+    async fn summarise(
+        diff: &str,
+        symbol_context: Option<String>,
+        commit_messages: &str,
+        categories: &str,
+    ) -> Result<Self> {
+        let mut prompt = Prompt::new()
+            .untrusted("Diff", diff)
+            .untrusted("CommitMessages", commit_messages)
+            .tag("Categories", categories);
+
+        if let Some(symbol_context) = &symbol_context {
+            prompt = prompt.untrusted("SymbolContext", symbol_context);
         }
-        .send()
-        .await
+
+        let user_prompt = prompt.build();
+
+        Self::send(user_prompt, SYSTEM_PROMPT).await
+    }
+
+    async fn merge(
+        partial_summaries: &[Self],
+        commit_messages: &str,
+        categories: &str,
+    ) -> Result<Self> {
+        tracing::info!(
+            "Merging {} partial release summaries",
+            partial_summaries.len()
+        );
+
+        let partial_summaries = partial_summaries
+            .iter()
+            .map(|s| serde_json::to_string(&s.items))
+            .collect::<Result<Vec<_>, _>>()?
+            .join("\n");
+
+        let user_prompt = Prompt::new()
+            .untrusted("PartialSummaries", &partial_summaries)
+            .untrusted("CommitMessages", commit_messages)
+            .tag("Categories", categories)
+            .build();
+
+        Self::send(user_prompt, MERGE_SYSTEM_PROMPT).await
+    }
+
+    async fn send(user_prompt: String, system_prompt: &'static str) -> Result<Self> {
+        Provider::from_config_or(Provider::ChatGpt)
+            .send(llm::Request {
+                user_prompt,
+                system_prompt,
+                schema_name: "json_objects_array",
+                schema: response_schema(),
+                max_tokens: Some(4096),
+                ..Default::default()
+            })
+            .await
+    }
+
+    pub fn is_partial(&self) -> bool {
+        !self.truncated_files.is_empty() || !self.dropped_files.is_empty()
     }
 }
 
@@ -36,6 +150,14 @@ pub struct SummaryCategory {
     pub items: Vec<String>,
 }
 
+fn get_positive_config_number(var_name: &str, default: usize) -> usize {
+    let number = config::get_number(var_name, default);
+
+    assert!(number > 0, "{var_name} should be a positive number");
+
+    number
+}
+
 fn response_schema() -> Value {
     let category_title = json!({
         "type": "string",
@@ -69,24 +191,15 @@ fn response_schema() -> Value {
         "items": category
     });
 
-    let schema = json!({
-        "name": "json_objects_array",
-        "schema": {
-            "type": "object",
-            "properties": {
-                "items": categories
-            },
-            "required": [
-                "items"
-            ],
-            "additionalProperties": false
-        },
-        "strict": true
-    });
-
     json!({
-        "type": "json_schema",
-        "json_schema": schema
+        "type": "object",
+        "properties": {
+            "items": categories
+        },
+        "required": [
+            "items"
+        ],
+        "additionalProperties": false
     })
 }
 
@@ -99,6 +212,9 @@ const SYSTEM_PROMPT: &str = "
         Avoid describing how a feature will impact a user or experience, just describe what the feature is and what it does.
         Avoid expanding acronyms, for example PLP, PDP or USP, to their full meanings because the users understand those.
         List any dependency additions, updates, or removals that were made in the package management files only.
+        If any categories are listed in the Categories tags, group the changes under only those categories instead, using their titles as written.
+        The SymbolContext tags, when given, contain code from elsewhere in the repository: the definitions of functions and types the changes use, and the call sites of the functions they change.
+        Use it to understand what the changes affect, such as which features call a changed function, but only summarise changes that are in the diff.
     </Instructions>
     <Steps>
         Analyse the Diff: Examine the git code diff to understand the changes in the codebase.
@@ -133,3 +249,22 @@ const SYSTEM_PROMPT: &str = "
             • Fixed an issue where the Twitter hyperlink was not displaying properly.
     </ExampleOutPut3>
 ";
+
+const MERGE_SYSTEM_PROMPT: &str = "
+    <Instructions>
+        Your role is to merge partial release summaries into a single release summary.
+        Each partial summary was written for a different part of the same release's git code diff and is a JSON array of categories, each with a title and items.
+        Combine categories with the same or similar titles, like New features, Improvements, Bug fixes and Dependency changes.
+        Merge items that describe the same feature or change into a single item, and remove duplicates.
+        Keep each item concise and non-technical, so that a non-technical team member can understand the change in simple terms.
+        Use the commit messages for extra context when deciding which items describe the same change.
+        Avoid adding features or changes that are not present in the partial summaries.
+        If any categories are listed in the Categories tags, only use those categories, using their titles as written.
+    </Instructions>
+    <Steps>
+        Read the Partial Summaries: Understand the features and changes each partial summary describes.
+        Group Categories: Combine categories that share a title or meaning.
+        Deduplicate Items: Merge items that describe the same change and keep each one to a sentence.
+        Exclude Empty Categories: Only include categories that have items to list.
+    </Steps>
+";
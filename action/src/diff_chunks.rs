@@ -0,0 +1,51 @@
+use shared::utils::diff;
+
+/// A diff split into per-file chunks that each fit within a token budget.
+#[derive(Debug, Default)]
+pub struct DiffChunks {
+    pub chunks: Vec<String>,
+    /// Files cut short because their diff alone exceeded the budget.
+    pub truncated_files: Vec<String>,
+    /// Files left out entirely because the maximum number of chunks was reached.
+    pub dropped_files: Vec<String>,
+}
+
+impl DiffChunks {
+    pub fn new(diff: &str, token_budget: usize, max_chunks: usize) -> Self {
+        let mut diff_chunks = Self::default();
+        let mut chunk = String::new();
+
+        for file in diff::split_by_file(diff) {
+            let mut content = file.content;
+
+            if diff::estimate_tokens(content) > token_budget {
+                content = diff::truncate_to_tokens(content, token_budget);
+                diff_chunks.truncated_files.push(file.path.to_string());
+            }
+
+            let chunk_tokens = diff::estimate_tokens(&chunk) + diff::estimate_tokens(content);
+
+            if !chunk.is_empty() && chunk_tokens > token_budget {
+                diff_chunks.chunks.push(std::mem::take(&mut chunk));
+            }
+
+            if diff_chunks.chunks.len() == max_chunks {
+                diff_chunks.truncated_files.retain(|f| f != file.path);
+                diff_chunks.dropped_files.push(file.path.to_string());
+                continue;
+            }
+
+            if !chunk.is_empty() {
+                chunk.push('\n');
+            }
+
+            chunk.push_str(content);
+        }
+
+        if !chunk.is_empty() {
+            diff_chunks.chunks.push(chunk);
+        }
+
+        diff_chunks
+    }
+}
@@ -1,4 +1,5 @@
 mod ai;
+mod diff_chunks;
 mod git;
 mod slack;
 mod target_paths;
@@ -10,10 +11,10 @@ use git::Git;
 use regex_lite::Regex;
 use shared::{
     services::{
-        github::{PullRequest, Repository},
+        github::{Client, PullRequest, Repository, repo_config::JiraConfig},
         jira::Issue,
     },
-    utils::{config, error::AppError},
+    utils::{code_index::CodeIndex, config, error::AppError},
 };
 use std::collections::HashSet;
 use target_paths::TargetPaths;
@@ -31,30 +32,38 @@ async fn main() -> Result<(), AppError> {
         .with_target(false)
         .init();
 
-    let run = WorkflowRun::get_by_id(&repo_name, &run_id).await?;
+    let client = Client::from_env().await?;
+    let run = WorkflowRun::get_by_id(&client, &repo_name, &run_id).await?;
 
-    if run.has_prev_successful_attempt().await? {
+    if run.has_prev_successful_attempt(&client).await? {
         tracing::warn!("Already previously deployed, skipping");
         return Ok(());
     }
 
-    if let Some(prev_runs) = WorkflowRuns::get_prev_runs_with_last_success_for_branch(&run).await? {
-        handle_master_release(run, prev_runs).await
+    if let Some(prev_runs) =
+        WorkflowRuns::get_prev_runs_with_last_success_for_branch(&client, &run).await?
+    {
+        handle_master_release(&client, run, prev_runs).await
     } else {
         tracing::info!("No previous successful run found for branch, summarising run commit");
-        handle_non_master_release(run).await
+        handle_non_master_release(&client, run).await
     }
 }
 
-async fn handle_master_release(run: WorkflowRun, prev_runs: PrevRuns) -> Result<(), AppError> {
-    let repo = run.get_repo().await?;
+async fn handle_master_release(
+    client: &Client,
+    run: WorkflowRun,
+    prev_runs: PrevRuns,
+) -> Result<(), AppError> {
+    let repo = run.get_repo(client).await?;
+    let repo_config = repo.get_repo_config(client).await?;
     let app_name = config::get_optional("APP_NAME").unwrap_or(repo.name.clone());
 
     let new_commit = &run.head_sha;
     let old_commit = &prev_runs.last_successful.head_sha;
 
     let mut diff = repo
-        .get_diff_between_commits(old_commit, new_commit)
+        .get_diff_between_commits(client, old_commit, new_commit)
         .await?;
 
     if diff.is_empty() {
@@ -63,10 +72,10 @@ async fn handle_master_release(run: WorkflowRun, prev_runs: PrevRuns) -> Result<
     }
 
     let target_paths = repo
-        .get_file(&run.path)
+        .get_file(client, &run.path)
         .await
         .and_then(WorkflowConfig::from_file)
-        .map(TargetPaths::new)?;
+        .map(|workflow_config| TargetPaths::new(workflow_config, &repo_config))?;
 
     diff = target_paths.filter_diff(&diff);
 
@@ -75,16 +84,26 @@ async fn handle_master_release(run: WorkflowRun, prev_runs: PrevRuns) -> Result<
         return Ok(());
     }
 
-    let commit_messages = Git::init(&repo.full_name).await?.get_commit_messages(
-        old_commit,
-        new_commit,
-        &target_paths,
-    )?;
-    let pull_requests = get_pull_requests(&run, Some(&prev_runs.prev_runs), &repo).await?;
+    let git = Git::init(client, &repo.full_name)?;
+    let commit_messages = git.get_commit_messages(old_commit, new_commit, &target_paths)?;
+
+    // The summary still works without the index, so errors only lose the extra context
+    let code_index = git
+        .get_source_files(new_commit)
+        .map(CodeIndex::new)
+        .inspect_err(|e| tracing::warn!("Error indexing {new_commit}, skipping: {e}"))
+        .ok();
+
+    let pull_requests = get_pull_requests(client, &run, Some(&prev_runs.prev_runs), &repo).await?;
 
     let (jira_issues, summary) = try_join(
-        get_jira_issues(&pull_requests, &commit_messages),
-        ai::ReleaseSummary::new(&diff, &commit_messages),
+        get_jira_issues(&repo_config.jira, &pull_requests, &commit_messages),
+        ai::ReleaseSummary::new(
+            &diff,
+            &commit_messages,
+            &repo_config.summary.categories,
+            code_index.as_ref(),
+        ),
     )
     .await?;
 
@@ -100,31 +119,42 @@ async fn handle_master_release(run: WorkflowRun, prev_runs: PrevRuns) -> Result<
         jira_issues,
         pull_requests,
         run: &run,
+        slack_config: &repo_config.notifications.slack,
         summary,
     }
     .send()
     .await
 }
 
-async fn handle_non_master_release(run: WorkflowRun) -> Result<(), AppError> {
-    let repo = run.get_repo().await?;
+async fn handle_non_master_release(client: &Client, run: WorkflowRun) -> Result<(), AppError> {
+    let repo = run.get_repo(client).await?;
+    let repo_config = repo.get_repo_config(client).await?;
     let app_name = config::get_optional("APP_NAME").unwrap_or(repo.name.clone());
 
     let (diff, pull_requests, commit_message) = try_join3(
-        repo.get_diff_for_commit(&run.head_sha),
-        get_pull_requests(&run, None, &repo),
-        repo.get_commit_message(&run.head_sha),
+        repo.get_diff_for_commit(client, &run.head_sha),
+        get_pull_requests(client, &run, None, &repo),
+        repo.get_commit_message(client, &run.head_sha),
     )
     .await?;
 
-    let prev_run = WorkflowRuns::get_prev_successful_run(&run).await?;
+    let prev_run = WorkflowRuns::get_prev_successful_run(client, &run).await?;
     let prev_run_url = prev_run.as_ref().map(|run| run.get_run_url());
     let diff_url = repo.get_commit_url(&run.head_sha);
     let compare_to_master_url = repo.get_compare_to_master_url(&run.head_sha);
 
     let (jira_issues, summary) = try_join(
-        get_jira_issues(&pull_requests, &[commit_message.clone()]),
-        ai::ReleaseSummary::new(&diff, &[commit_message]),
+        get_jira_issues(
+            &repo_config.jira,
+            &pull_requests,
+            std::slice::from_ref(&commit_message),
+        ),
+        ai::ReleaseSummary::new(
+            &diff,
+            std::slice::from_ref(&commit_message),
+            &repo_config.summary.categories,
+            None,
+        ),
     )
     .await?;
 
@@ -136,6 +166,7 @@ async fn handle_non_master_release(run: WorkflowRun) -> Result<(), AppError> {
         jira_issues,
         pull_requests,
         run: &run,
+        slack_config: &repo_config.notifications.slack,
         summary,
     }
     .send()
@@ -143,12 +174,13 @@ async fn handle_non_master_release(run: WorkflowRun) -> Result<(), AppError> {
 }
 
 async fn get_pull_requests(
+    client: &Client,
     curr_run: &WorkflowRun,
     prev_runs: Option<&[WorkflowRun]>,
     repo: &Repository,
 ) -> Result<Vec<PullRequest>> {
     let mut pull_requests = repo
-        .get_pull_requests_for_commit(&curr_run.head_sha)
+        .get_pull_requests_for_commit(client, &curr_run.head_sha)
         .await?;
 
     let Some(prev_runs) = prev_runs else {
@@ -157,7 +189,7 @@ async fn get_pull_requests(
 
     for prev_run in prev_runs {
         let prs = repo
-            .get_pull_requests_for_commit(&prev_run.head_sha)
+            .get_pull_requests_for_commit(client, &prev_run.head_sha)
             .await?;
 
         pull_requests.extend(prs);
@@ -171,12 +203,11 @@ async fn get_pull_requests(
 }
 
 pub async fn get_jira_issues(
+    jira_config: &JiraConfig,
     pull_requests: &[PullRequest],
     commit_messages: &[String],
 ) -> Result<Vec<Issue>> {
-    let jira_enabled = config::get_optional("JIRA_API_KEY").is_some();
-
-    if !jira_enabled {
+    if !jira_config.is_enabled() {
         return Ok(Vec::new());
     }
 
@@ -204,7 +235,11 @@ pub async fn get_jira_issues(
         }
     }
 
-    let requests = keys.into_iter().map(Issue::get_by_key).collect::<Vec<_>>();
+    let requests = keys
+        .into_iter()
+        .filter(|key| jira_config.is_issue_included(key))
+        .map(Issue::get_by_key)
+        .collect::<Vec<_>>();
 
     let mut issues: Vec<_> = try_join_all(requests)
         .await?
@@ -2,7 +2,10 @@ use super::workflows::WorkflowRun;
 use crate::ai;
 use serde_json::{json, Value};
 use shared::{
-    services::{github::PullRequest, jira::Issue},
+    services::{
+        github::{PullRequest, repo_config::SlackConfig},
+        jira::Issue,
+    },
     utils::{config, error::AppError},
 };
 
@@ -14,12 +17,14 @@ pub struct ReleaseSummary<'a> {
     pub prev_run_url: Option<&'a String>,
     pub pull_requests: Vec<PullRequest>,
     pub run: &'a WorkflowRun,
+    pub slack_config: &'a SlackConfig,
     pub summary: ai::ReleaseSummary,
 }
 
 impl ReleaseSummary<'_> {
     pub async fn send(&self) -> Result<(), AppError> {
-        let send_slack_msg = config::get("SLACK_MESSAGE_ENABLED") == "true";
+        let send_slack_msg =
+            config::get("SLACK_MESSAGE_ENABLED") == "true" && self.slack_config.enabled;
 
         if !send_slack_msg {
             println!("{:#?}", self.summary);
@@ -32,6 +37,10 @@ impl ReleaseSummary<'_> {
 
         message_blocks.extend(self.get_summary_block());
 
+        if self.summary.is_partial() {
+            message_blocks.push(self.get_summary_coverage_block());
+        }
+
         if !self.jira_issues.is_empty() || !self.pull_requests.is_empty() {
             message_blocks.push(json!({ "type": "divider" }));
         }
@@ -49,7 +58,7 @@ impl ReleaseSummary<'_> {
         message_blocks.push(self.get_metadata_block());
 
         reqwest::Client::new()
-            .put(config::get("SLACK_WEBHOOK_URL"))
+            .put(self.slack_config.get_webhook_url())
             .json(&json!({"blocks": json!(message_blocks)}))
             .send()
             .await?
@@ -93,6 +102,35 @@ impl ReleaseSummary<'_> {
         blocks
     }
 
+    fn get_summary_coverage_block(&self) -> Value {
+        let mut elements = Vec::new();
+
+        if !self.summary.truncated_files.is_empty() {
+            elements.push(json!({
+                "type": "mrkdwn",
+                "text": format!(
+                    ":warning: Summarised with less detail: {}",
+                    format_file_list(&self.summary.truncated_files)
+                )
+            }));
+        }
+
+        if !self.summary.dropped_files.is_empty() {
+            elements.push(json!({
+                "type": "mrkdwn",
+                "text": format!(
+                    ":warning: Too large to summarise: {}",
+                    format_file_list(&self.summary.dropped_files)
+                )
+            }));
+        }
+
+        json!({
+            "type": "context",
+            "elements": elements
+        })
+    }
+
     fn get_pull_requests_block(&self) -> Value {
         json!({
             "type": "rich_text",
@@ -238,3 +276,21 @@ impl ReleaseSummary<'_> {
         })
     }
 }
+
+fn format_file_list(files: &[String]) -> String {
+    // Keep well under Slack's 3000 character limit for a text element
+    const MAX_FILES: usize = 20;
+
+    let mut list = files
+        .iter()
+        .take(MAX_FILES)
+        .map(|f| format!("`{f}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if files.len() > MAX_FILES {
+        list.push_str(&format!(" and {} more", files.len() - MAX_FILES));
+    }
+
+    list
+}
@@ -1,7 +1,10 @@
 use super::workflows::WorkflowConfig;
 use glob::Pattern;
 use regex_lite::Regex;
-use shared::{services::github::IGNORED_REPO_PATHS, utils::config};
+use shared::{
+    services::github::{IGNORED_REPO_PATHS, RepoConfig},
+    utils::config,
+};
 
 #[derive(Debug, Default)]
 pub struct TargetPaths {
@@ -10,7 +13,9 @@ pub struct TargetPaths {
 }
 
 impl TargetPaths {
-    pub fn new(workflow_config: WorkflowConfig) -> Self {
+    /// Uses the `paths` action input if set, then the paths in `.anno.yml`, and finally the
+    /// workflow's `push` trigger paths.
+    pub fn new(workflow_config: WorkflowConfig, repo_config: &RepoConfig) -> Self {
         if let Some(target_paths) = Self::get_paths_from_action_input() {
             let (included, excluded) = Self::split_paths(&target_paths);
 
@@ -20,6 +25,17 @@ impl TargetPaths {
             };
         }
 
+        if repo_config.has_paths() {
+            let (included, mut excluded) = Self::split_paths(&repo_config.paths);
+
+            excluded.extend(&repo_config.paths_ignore);
+
+            return Self {
+                included: Self::create_patterns(included),
+                excluded: Self::create_patterns(excluded),
+            };
+        }
+
         let Some(push_config) = workflow_config.push_config() else {
             return Self::default();
         };
@@ -49,15 +65,15 @@ impl TargetPaths {
         let mut is_inside_ignored_file = false;
         diff.lines()
             .filter(|line| {
-                if line.starts_with("diff --git") {
-                    if let Some(caps) = re.captures(line) {
-                        let path = caps[1].to_string();
+                if line.starts_with("diff --git")
+                    && let Some(caps) = re.captures(line)
+                {
+                    let path = caps[1].to_string();
 
-                        let is_ignored_file = IGNORED_REPO_PATHS.iter().any(|p| path.contains(p));
-                        let is_non_target_file = !self.is_path_included(&path);
+                    let is_ignored_file = IGNORED_REPO_PATHS.iter().any(|p| path.contains(p));
+                    let is_non_target_file = !self.is_path_included(&path);
 
-                        is_inside_ignored_file = is_ignored_file || is_non_target_file;
-                    }
+                    is_inside_ignored_file = is_ignored_file || is_non_target_file;
                 }
 
                 !is_inside_ignored_file
@@ -1,9 +1,8 @@
 use anyhow::Result;
-use base64::prelude::*;
 use serde::Deserialize;
 use shared::{
     services::github::{
-        AccessToken,
+        Client,
         repository::{RepoFile, Repository},
     },
     utils::{config, error::AppError},
@@ -16,6 +15,7 @@ pub struct WorkflowRuns {
 
 impl WorkflowRuns {
     pub async fn get_prev_runs_with_last_success_for_branch(
+        client: &Client,
         run: &WorkflowRun,
     ) -> Result<Option<PrevRuns>, AppError> {
         tracing::info!(
@@ -26,7 +26,9 @@ impl WorkflowRuns {
         let mut all_prev_runs: Vec<WorkflowRun> = Vec::new();
         let mut page = 1;
         loop {
-            let prev_runs = Self::get_prev_runs(run, true, page).await?.workflow_runs;
+            let prev_runs = Self::get_prev_runs(client, run, true, page)
+                .await?
+                .workflow_runs;
 
             if prev_runs.is_empty() {
                 return Ok(None);
@@ -37,7 +39,7 @@ impl WorkflowRuns {
                     continue;
                 }
 
-                if prev_run.has_successful_attempt().await? {
+                if prev_run.has_successful_attempt(client).await? {
                     return Ok(Some(PrevRuns {
                         last_successful: prev_run,
                         prev_runs: all_prev_runs,
@@ -52,13 +54,16 @@ impl WorkflowRuns {
     }
 
     pub async fn get_prev_successful_run(
+        client: &Client,
         run: &WorkflowRun,
     ) -> Result<Option<WorkflowRun>, AppError> {
         tracing::info!("Fetching last successful run for workflow");
 
         let mut page = 1;
         loop {
-            let prev_runs = Self::get_prev_runs(run, false, page).await?.workflow_runs;
+            let prev_runs = Self::get_prev_runs(client, run, false, page)
+                .await?
+                .workflow_runs;
 
             if prev_runs.is_empty() {
                 return Ok(None);
@@ -69,7 +74,7 @@ impl WorkflowRuns {
                     continue;
                 }
 
-                if prev_run.has_successful_attempt().await? {
+                if prev_run.has_successful_attempt(client).await? {
                     return Ok(Some(prev_run));
                 }
             }
@@ -79,12 +84,13 @@ impl WorkflowRuns {
     }
 
     async fn get_prev_runs(
+        client: &Client,
         run: &WorkflowRun,
         for_run_branch: bool,
         page: u8,
     ) -> Result<Self, AppError> {
         let gh_base_url = config::get("GITHUB_BASE_URL");
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
 
         let url = format!(
             "{}/repos/{}/actions/runs",
@@ -142,10 +148,10 @@ pub struct WorkflowRun {
 }
 
 impl WorkflowRun {
-    pub async fn get_by_id(repo_name: &String, run_id: &String) -> Result<Self> {
+    pub async fn get_by_id(client: &Client, repo_name: &String, run_id: &String) -> Result<Self> {
         tracing::info!("Fetching workflow run {run_id}");
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
         let url = format!("https://api.github.com/repos/{repo_name}/actions/runs/{run_id}");
 
         let workflow_run = reqwest::Client::new()
@@ -167,34 +173,34 @@ impl WorkflowRun {
         self.conclusion.as_ref().is_some_and(|c| c == "success")
     }
 
-    pub async fn has_successful_attempt(&self) -> Result<bool, AppError> {
-        Ok(self.is_successful_attempt() || self.get_prev_successful_attempt().await?.is_some())
+    pub async fn has_successful_attempt(&self, client: &Client) -> Result<bool, AppError> {
+        Ok(self.is_successful_attempt()
+            || self.get_prev_successful_attempt(client).await?.is_some())
     }
 
-    pub async fn has_prev_successful_attempt(&self) -> Result<bool, AppError> {
-        Ok(self.get_prev_successful_attempt().await?.is_some())
+    pub async fn has_prev_successful_attempt(&self, client: &Client) -> Result<bool, AppError> {
+        Ok(self.get_prev_successful_attempt(client).await?.is_some())
     }
 
-    async fn get_prev_successful_attempt(&self) -> Result<Option<WorkflowRun>, AppError> {
-        let mut possible_prev_attempt = self.get_prev_attempt().await?;
-
-        loop {
-            let Some(prev_attempt) = possible_prev_attempt else {
-                break;
-            };
+    async fn get_prev_successful_attempt(
+        &self,
+        client: &Client,
+    ) -> Result<Option<WorkflowRun>, AppError> {
+        let mut possible_prev_attempt = self.get_prev_attempt(client).await?;
 
+        while let Some(prev_attempt) = possible_prev_attempt {
             if prev_attempt.is_successful_attempt() {
                 return Ok(Some(prev_attempt));
             }
 
-            possible_prev_attempt = prev_attempt.get_prev_attempt().await?;
+            possible_prev_attempt = prev_attempt.get_prev_attempt(client).await?;
         }
 
         Ok(None)
     }
 
-    async fn get_prev_attempt(&self) -> Result<Option<WorkflowRun>, AppError> {
-        let gh_token = AccessToken::get().await?;
+    async fn get_prev_attempt(&self, client: &Client) -> Result<Option<WorkflowRun>, AppError> {
+        let gh_token = client.token();
 
         let Some(prev_attempt_url) = &self.previous_attempt_url else {
             return Ok(None);
@@ -219,10 +225,10 @@ impl WorkflowRun {
         &self.html_url
     }
 
-    pub async fn get_repo(&self) -> Result<Repository> {
+    pub async fn get_repo(&self, client: &Client) -> Result<Repository> {
         tracing::info!("Fetching workflow repository");
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
 
         let repo = reqwest::Client::new()
             .get(&self.repository.url)
@@ -247,9 +253,7 @@ pub struct WorkflowConfig {
 
 impl WorkflowConfig {
     pub fn from_file(file: RepoFile) -> Result<Self> {
-        let decoded_config = BASE64_STANDARD.decode(file.content.replace('\n', ""))?;
-        let config_content = String::from_utf8(decoded_config)?;
-        let config = serde_yaml::from_str(&config_content)?;
+        let config = serde_yaml::from_str(&file.decode()?)?;
 
         Ok(config)
     }
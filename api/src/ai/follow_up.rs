@@ -0,0 +1,88 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use shared::{
+    services::llm::{self, Llm},
+    utils::prompt::Prompt,
+};
+
+/// An answer to a developer's reply on one of Anno's findings.
+#[derive(Deserialize)]
+pub struct FollowUp {
+    decision: Decision,
+    pub answer: String,
+}
+
+#[derive(Deserialize)]
+enum Decision {
+    Withdraw,
+    Explain,
+}
+
+impl FollowUp {
+    pub async fn new(finding: &str, diff_hunk: &str, reply: &str) -> Result<Self> {
+        tracing::info!("Generating follow-up answer");
+
+        let user_prompt = Prompt::new()
+            .tag("Finding", finding)
+            .untrusted("DiffHunk", diff_hunk)
+            .untrusted("Reply", reply)
+            .build();
+
+        super::provider()
+            .send(llm::Request {
+                user_prompt,
+                system_prompt: SYSTEM_PROMPT,
+                schema_name: "follow_up",
+                schema: response_schema(),
+                ..Default::default()
+            })
+            .await
+    }
+
+    pub fn is_withdrawn(&self) -> bool {
+        matches!(self.decision, Decision::Withdraw)
+    }
+}
+
+fn response_schema() -> Value {
+    let decision = json!({
+      "type": "string",
+      "enum": [
+        "Withdraw",
+        "Explain"
+      ],
+      "description": "Withdraw if the reply shows the finding is wrong or intended, otherwise Explain."
+    });
+
+    let answer = json!({
+      "type": "string",
+      "description": "A short markdown answer to the developer's reply."
+    });
+
+    json!({
+      "type": "object",
+      "properties": {
+        "decision": decision,
+        "answer": answer
+      },
+      "required": [
+        "decision",
+        "answer"
+      ],
+      "additionalProperties": false
+    })
+}
+
+const SYSTEM_PROMPT: &str = "
+    <Instructions>
+        You previously reviewed a pull request and raised the finding in the Finding tags on the code in the DiffHunk tags.
+        A developer has replied to the finding with the message in the Reply tags, for example to say the code is intentional or to ask a question.
+        Decide whether to withdraw the finding or to explain it further.
+        Withdraw the finding if the reply shows that it is wrong, that the behaviour is intended, or that it is handled elsewhere in a way that is consistent with the code you can see.
+        Otherwise, explain the finding further by answering the developer's reply, using the code in the diff hunk to show why it is still a problem.
+        Don't withdraw a finding just because the developer disagrees; only withdraw it when their reason holds up.
+        When withdrawing, briefly acknowledge the developer's reason.
+        Keep your answer short, polite and to the point, and double check it is valid markdown.
+    </Instructions>
+";
@@ -1,5 +1,28 @@
+pub mod follow_up;
+pub mod pr_explanation;
 pub mod pr_review;
 pub mod pr_summary;
+pub mod pr_template_summary;
+pub mod security_review;
 
+pub use follow_up::*;
+pub use pr_explanation::*;
 pub use pr_review::*;
 pub use pr_summary::*;
+pub use pr_template_summary::*;
+pub use security_review::*;
+
+use shared::services::llm::Provider;
+
+/// Explains the context added by [`CodeContext`] and how secrets are redacted, shared by the
+/// system prompts of the bug and security reviews.
+const CODE_CONTEXT_INSTRUCTIONS: &str = "
+        The FileContext tags, when given, contain the new versions of the changed files, or the code around each hunk for larger files, so you can see code defined outside the diff.
+        Each FileContext line starts with its line number, followed by '+' if the pull request added or changed it; other lines are unchanged context.
+        The SymbolContext tags, when given, contain code from elsewhere in the repository: the definitions of functions and types the changes use, and the call sites of the functions they change.
+        Secrets in the diff and file context have been replaced with '[REDACTED <kind>]' markers. Don't add findings for them, as they are reported separately.";
+
+/// PR analysis defaults to Claude unless `LLM_PROVIDER` is set.
+pub fn provider() -> Provider {
+    Provider::from_config_or(Provider::Claude)
+}
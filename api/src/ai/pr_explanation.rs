@@ -0,0 +1,69 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use shared::{
+    services::llm::{self, Llm},
+    utils::prompt::Prompt,
+};
+
+#[derive(Deserialize)]
+pub struct PrExplanation {
+    pub explanation: String,
+}
+
+impl PrExplanation {
+    pub async fn new(file_diff: &str, commit_messages: &[String]) -> Result<Self> {
+        tracing::info!("Generating PR file explanation");
+
+        let commit_messages = commit_messages.join("\n");
+
+        let user_prompt = Prompt::new()
+            .untrusted("Diff", file_diff)
+            .untrusted("CommitMessages", &commit_messages)
+            .build();
+
+        super::provider()
+            .send(llm::Request {
+                user_prompt,
+                system_prompt: SYSTEM_PROMPT,
+                schema_name: "pr_explanation",
+                schema: response_schema(),
+                ..Default::default()
+            })
+            .await
+    }
+}
+
+fn response_schema() -> Value {
+    let explanation = json!({
+      "type": "string",
+      "description": "A markdown block of text explaining the changes to the file."
+    });
+
+    json!({
+      "type": "object",
+      "properties": {
+        "explanation": explanation,
+      },
+      "required": [
+        "explanation",
+      ],
+      "additionalProperties": false
+    })
+}
+
+const SYSTEM_PROMPT: &str = "
+    <Instructions>
+        Your task is to explain the changes made to a single file in a pull request to a developer who is reviewing it.
+        Use the diff and commit messages to explain what the changes do and why they were likely made.
+        Pay attention to what has been deleted (denoted by '-') or added (denoted by '+') so that you only describe the code as it is after the change.
+        Walk through the changes in the order they appear in the file, grouping related changes together.
+        Keep your explanation short and clear, and avoid giving opinions or suggesting improvements.
+        Format your explanation in markdown.
+    </Instructions>
+    <Steps>
+        - Review the diff to understand the changes made to the file.
+        - Review the commit messages to understand the context of the changes.
+        - Explain what the changes do and how they fit together.
+    </Steps>
+";
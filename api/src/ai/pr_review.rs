@@ -1,102 +1,598 @@
+use super::{CODE_CONTEXT_INSTRUCTIONS, SecurityReview};
 use anyhow::Result;
 use serde::Deserialize;
 use serde_json::{json, Value};
-use shared::services::claude;
+use shared::{
+    services::{
+        github::{
+            check_run::{Annotation, AnnotationLevel, CheckRun, Conclusion},
+            metadata::{self, CommentKind, CommentMetadata},
+            pull_request::{DiffPositions, ReviewComment},
+            repo_config::{ReviewConfig, Severity},
+        },
+        llm::{self, Llm},
+    },
+    utils::{config, prompt::Prompt, redact},
+};
+use std::sync::LazyLock;
+
+/// Recorded in review comments so reviews from older prompts can be told apart. Bump it when
+/// the prompt or schema changes.
+pub const PROMPT_VERSION: &str = "7";
 
 #[derive(Deserialize)]
 pub struct PrReview {
+    /// Worked out from the findings rather than left to the model, so it follows the repo's
+    /// `blocking_severity`.
+    #[serde(skip)]
     verdict: Verdict,
-    pub feedback: String,
+    /// The model's own answer to whether the diff has issues, which is checked against its
+    /// findings.
+    #[serde(default)]
+    has_issues: bool,
+    /// Set when the model says there are issues but doesn't report any findings.
+    #[serde(skip)]
+    is_contradictory: bool,
+    pub summary: String,
+    pub findings: Vec<Finding>,
+    /// Bugs from the previous review that the new commits appear to fix.
+    #[serde(default)]
+    pub resolved_findings: Vec<String>,
+    /// Bugs from the previous review that are still present.
+    #[serde(default)]
+    pub unresolved_findings: Vec<String>,
+}
+
+/// Context for reviewing only the commits pushed since Anno's previous review.
+pub struct Incremental<'a> {
+    /// Short SHA of the commit the previous review was written for.
+    pub prev_sha: &'a str,
+    pub prev_review: &'a str,
+    /// The whole PR diff, which is background context for the diff being reviewed.
+    pub full_diff: &'a str,
+}
+
+/// Code from outside the diff that helps review it.
+#[derive(Default)]
+pub struct CodeContext {
+    /// The new versions of the changed files.
+    pub files: Option<String>,
+    /// Definitions and call sites of the symbols the diff touches.
+    pub symbols: Option<String>,
+}
+
+impl CodeContext {
+    pub(super) fn add_to_prompt(&self, mut prompt: Prompt) -> Prompt {
+        if let Some(files) = &self.files {
+            prompt = prompt.untrusted("FileContext", files);
+        }
+
+        if let Some(symbols) = &self.symbols {
+            prompt = prompt.untrusted("SymbolContext", symbols);
+        }
+
+        prompt
+    }
 }
 
 impl PrReview {
-    pub async fn new(diff: &str, commit_messages: &[String]) -> Result<Self> {
+    pub async fn new(
+        diff: &str,
+        commit_messages: &[String],
+        review_config: &ReviewConfig,
+        guidelines: Option<&str>,
+        code_context: &CodeContext,
+        incremental: Option<&Incremental<'_>>,
+    ) -> Result<Self> {
         tracing::info!("Generating PR analysis");
 
         let commit_messages = commit_messages.join("\n");
+        let min_severity = review_config.get_min_severity();
+        let guidelines = guidelines.unwrap_or_default();
+
+        let prompt = Prompt::new()
+            .untrusted("Diff", diff)
+            .untrusted("CommitMessages", &commit_messages)
+            .tag("MinimumSeverity", min_severity.as_str())
+            .tag("Guidelines", guidelines);
+
+        let mut prompt = code_context.add_to_prompt(prompt);
+
+        if let Some(incremental) = incremental {
+            prompt = prompt
+                .untrusted("PreviousReview", incremental.prev_review)
+                .untrusted("FullDiff", incremental.full_diff);
+        }
+
+        let mut review: Self = super::provider()
+            .send(llm::Request {
+                user_prompt: prompt.build(),
+                system_prompt: &SYSTEM_PROMPT,
+                schema_name: "pr_review",
+                schema: response_schema(),
+                ..Default::default()
+            })
+            .await?;
+
+        // A response like this can come from the model being talked out of its findings by content
+        // in the PR, so it isn't trusted to pass the review. It's checked before findings below
+        // the minimum are dropped, as those are issues the model was right to mention.
+        review.is_contradictory = review.has_issues && review.findings.is_empty();
+
+        if review.is_contradictory {
+            tracing::warn!(
+                "Review says there are issues without any findings, treating as negative"
+            );
+        }
+
+        // The model doesn't always stick to the minimum, so anything below it is dropped
+        review.findings.retain(|f| f.severity >= min_severity);
+        review.set_verdict(review_config);
+
+        Ok(review)
+    }
+
+    /// Adds the findings of a security review, which are all marked as security issues.
+    pub fn add_security_review(
+        &mut self,
+        security_review: SecurityReview,
+        review_config: &ReviewConfig,
+    ) {
+        let min_severity = review_config.get_min_severity();
+
+        let findings = security_review
+            .findings
+            .into_iter()
+            .filter(|f| f.severity >= min_severity)
+            .map(|f| Finding {
+                category: Category::Security,
+                ..f
+            })
+            .collect::<Vec<_>>();
+
+        if findings.is_empty() {
+            return;
+        }
+
+        if !security_review.summary.trim().is_empty() {
+            self.summary = format!(
+                "{}\n\n{}",
+                self.summary.trim(),
+                security_review.summary.trim()
+            )
+            .trim()
+            .to_string();
+        }
+
+        self.findings.extend(findings);
+        self.set_verdict(review_config);
+    }
 
-        let user_prompt = format!(
-            "<Diff>{diff}</Diff>
-             <CommitMessages>{commit_messages}</CommitMessages>"
-        );
+    /// Adds a blocker finding for each secret redacted from the diff, so the author knows to
+    /// revoke it.
+    pub fn add_redaction_findings(&mut self, diff: &str, review_config: &ReviewConfig) {
+        let redactions = redact::find_redactions(diff);
 
-        claude::Request {
-            user_prompt,
-            system_prompt: SYSTEM_PROMPT,
-            tool_schema: response_schema(),
-            tool_name: "pr_review",
-            ..Default::default()
+        if redactions.is_empty() {
+            return;
         }
-        .send()
-        .await
+
+        self.findings
+            .extend(redactions.into_iter().map(|redaction| Finding {
+                path: redaction.path,
+                start_line: redaction.line,
+                end_line: redaction.line,
+                severity: Severity::Blocker,
+                category: Category::Security,
+                explanation: format!(
+                    "Possible {} committed. It was hidden from this review, but anyone who can read the repository can see it, so it should be revoked and removed from the branch's history.",
+                    redaction.kind
+                ),
+                rule: String::new(),
+            }));
+
+        self.set_verdict(review_config);
+    }
+
+    fn set_verdict(&mut self, review_config: &ReviewConfig) {
+        let is_blocked = self
+            .findings
+            .iter()
+            .any(|f| f.severity >= review_config.blocking_severity);
+
+        self.verdict =
+            if is_blocked || self.is_contradictory || !self.unresolved_findings.is_empty() {
+                Verdict::Negative
+            } else {
+                Verdict::Positive
+            };
     }
 
     pub fn is_positive(&self) -> bool {
         matches!(self.verdict, Verdict::Positive)
     }
+
+    /// Gets the hidden metadata for the review comment, recording the commit it was written for.
+    pub fn get_metadata(&self, head_sha: &str) -> CommentMetadata {
+        let verdict = match self.verdict {
+            Verdict::Positive => metadata::Verdict::Positive,
+            Verdict::Negative => metadata::Verdict::Negative,
+        };
+
+        CommentMetadata {
+            kind: CommentKind::Review,
+            verdict: Some(verdict),
+            sha: Some(head_sha.to_string()),
+            prompt_version: Some(PROMPT_VERSION.to_string()),
+            model: Some(super::provider().get_model()),
+        }
+    }
+
+    /// Gets the review comment with the findings grouped by severity, noting what's changed
+    /// since the previous review for incremental reviews.
+    pub fn get_comment(&self, incremental: Option<&Incremental<'_>>) -> String {
+        let mut comment = String::new();
+
+        if let Some(incremental) = incremental {
+            comment.push_str(&format!(
+                "_Reviewed the commits pushed since {}._\n\n",
+                incremental.prev_sha
+            ));
+        }
+
+        if self.is_positive() {
+            comment.push_str("LGTM 👍");
+        } else {
+            comment.push_str("This PR may contain the following issues.");
+        }
+
+        if !self.summary.trim().is_empty() {
+            comment.push_str(&format!("\n\n{}", self.summary.trim()));
+        }
+
+        for severity in Severity::ALL {
+            let findings = self
+                .findings
+                .iter()
+                .filter(|f| f.severity == severity)
+                .collect::<Vec<_>>();
+
+            if findings.is_empty() {
+                continue;
+            }
+
+            comment.push_str(&format!("\n\n### {}\n", severity.get_label()));
+
+            for finding in findings {
+                comment.push_str(&format!(
+                    "\n- **{}** in `{}` (lines {}-{}): {}",
+                    finding.category.get_label(),
+                    finding.path,
+                    finding.start_line,
+                    finding.end_line,
+                    finding.get_message().replace('\n', "\n  ")
+                ));
+            }
+        }
+
+        if incremental.is_none() {
+            return comment;
+        }
+
+        for (title, findings) in [
+            ("Still present", &self.unresolved_findings),
+            ("Resolved", &self.resolved_findings),
+        ] {
+            if findings.is_empty() {
+                continue;
+            }
+
+            comment.push_str(&format!("\n\n**{title} from the previous review**\n"));
+
+            for finding in findings {
+                comment.push_str(&format!("\n- {finding}"));
+            }
+        }
+
+        comment
+    }
+
+    /// Anchors findings to their diff positions, returning the review body and inline comments.
+    /// Findings outside the diff can't be commented on inline so are listed in the body instead.
+    /// When `new_positions` is given, for the commits pushed since the previous review, findings
+    /// outside them are left out, as they were posted with an earlier review.
+    pub fn get_review(
+        &self,
+        diff_positions: &DiffPositions,
+        new_positions: Option<&DiffPositions>,
+    ) -> Option<(String, Vec<ReviewComment>)> {
+        let findings = self
+            .findings
+            .iter()
+            .filter(|f| {
+                new_positions.is_none_or(|positions| {
+                    positions.find(&f.path, f.start_line, f.end_line).is_some()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if findings.is_empty() {
+            return None;
+        }
+
+        let mut body = String::from("Anno found the following issues in this PR.");
+        let mut comments = Vec::new();
+
+        for finding in findings {
+            let position = diff_positions.find(&finding.path, finding.start_line, finding.end_line);
+
+            match position {
+                Some(position) => comments.push(ReviewComment {
+                    path: finding.path.clone(),
+                    position,
+                    body: finding.to_markdown(),
+                }),
+                None => body.push_str(&format!(
+                    "\n\n`{}` (lines {}-{}): {}",
+                    finding.path,
+                    finding.start_line,
+                    finding.end_line,
+                    finding.to_markdown()
+                )),
+            }
+        }
+
+        Some((body, comments))
+    }
+
+    /// Converts the review into a Check Run. Negative reviews fail the check, unless
+    /// `CHECK_RUN_ADVISORY` is set.
+    pub fn get_check_run(&self, head_sha: &str) -> CheckRun {
+        let is_advisory = config::get_optional("CHECK_RUN_ADVISORY").is_some_and(|a| a == "true");
+
+        let conclusion = match self.verdict {
+            Verdict::Positive => Conclusion::Success,
+            Verdict::Negative if is_advisory => Conclusion::Neutral,
+            Verdict::Negative => Conclusion::Failure,
+        };
+
+        let title = match self.findings.len() {
+            0 if self.is_positive() => "No issues found".to_string(),
+            0 => "Potential issues found".to_string(),
+            1 => "1 potential issue found".to_string(),
+            count => format!("{count} potential issues found"),
+        };
+
+        let annotations = self
+            .findings
+            .iter()
+            .map(|finding| Annotation {
+                path: finding.path.clone(),
+                start_line: finding.start_line.min(finding.end_line),
+                end_line: finding.end_line.max(finding.start_line),
+                annotation_level: match finding.severity {
+                    Severity::Blocker => AnnotationLevel::Failure,
+                    Severity::Major => AnnotationLevel::Warning,
+                    Severity::Minor | Severity::Info => AnnotationLevel::Notice,
+                },
+                message: finding.get_message(),
+            })
+            .collect();
+
+        CheckRun {
+            name: "Anno",
+            head_sha: head_sha.to_string(),
+            conclusion,
+            title,
+            summary: self.get_comment(None),
+            annotations,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Default, Debug)]
 enum Verdict {
+    #[default]
     Positive,
     Negative,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct Finding {
+    pub path: String,
+    pub start_line: u64,
+    pub end_line: u64,
+    pub severity: Severity,
+    pub category: Category,
+    pub explanation: String,
+    /// The team guideline the finding breaks, empty when it's a general bug.
+    #[serde(default)]
+    pub rule: String,
+}
+
+impl Finding {
+    fn to_markdown(&self) -> String {
+        format!(
+            "**{}** · {}: {}",
+            self.severity.get_label(),
+            self.category.get_label(),
+            self.get_message()
+        )
+    }
+
+    fn get_message(&self) -> String {
+        if self.rule.is_empty() {
+            return self.explanation.clone();
+        }
+
+        format!("{}\n\nGuideline: {}", self.explanation, self.rule)
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Bug,
+    Security,
+    Performance,
+    Concurrency,
+    ErrorHandling,
+}
+
+impl Category {
+    fn get_label(&self) -> &'static str {
+        match self {
+            Self::Bug => "Bug",
+            Self::Security => "Security",
+            Self::Performance => "Performance",
+            Self::Concurrency => "Concurrency",
+            Self::ErrorHandling => "Error handling",
+        }
+    }
+}
+
 fn response_schema() -> Value {
-    let verdict = json!({
+    let summary = json!({
       "type": "string",
-      "enum": [
-        "Positive",
-        "Negative"
-      ],
-      "description": "The verdict which can either be Positive or Negative."
+      "description": "A short markdown overview of the issues found, without repeating each finding. Empty if there are no issues."
     });
 
-    let feedback = json!({
-      "type": "string",
-      "description": "A markdown block of text providing feedback."
+    let has_issues = json!({
+      "type": "boolean",
+      "description": "Whether the diff has any issues at or above the minimum severity. True if and only if findings is non-empty."
+    });
+
+    let finding = finding_schema(&[
+        "bug",
+        "security",
+        "performance",
+        "concurrency",
+        "error_handling",
+    ]);
+
+    let findings = json!({
+      "type": "array",
+      "description": "Each bug mentioned in the feedback, anchored to the lines it occurs on. Empty if there are no bugs.",
+      "items": finding
+    });
+
+    let resolved_findings = json!({
+      "type": "array",
+      "description": "A short description of each bug from the previous review that the diff fixes. Empty if there is no previous review.",
+      "items": {
+        "type": "string"
+      }
     });
 
+    let unresolved_findings = json!({
+      "type": "array",
+      "description": "A short description of each bug from the previous review that is still present. Empty if there is no previous review.",
+      "items": {
+        "type": "string"
+      }
+    });
+
+    json!({
+      "type": "object",
+      "properties": {
+        "summary": summary,
+        "has_issues": has_issues,
+        "findings": findings,
+        "resolved_findings": resolved_findings,
+        "unresolved_findings": unresolved_findings
+      },
+      "required": [
+        "summary",
+        "has_issues",
+        "findings",
+        "resolved_findings",
+        "unresolved_findings"
+      ],
+      "additionalProperties": false
+    })
+}
+
+/// Schema of a finding, limited to the given categories.
+pub(super) fn finding_schema(categories: &[&str]) -> Value {
     json!({
-      "name": "pr_review",
-      "input_schema": {
-        "type": "object",
-        "properties": {
-          "verdict": verdict,
-          "feedback": feedback
+      "type": "object",
+      "properties": {
+        "path": {
+          "type": "string",
+          "description": "The path of the file containing the bug, as shown in the diff header without the 'a/' or 'b/' prefix."
+        },
+        "start_line": {
+          "type": "integer",
+          "description": "The first line of the bug in the new version of the file, calculated from the hunk headers."
+        },
+        "end_line": {
+          "type": "integer",
+          "description": "The last line of the bug in the new version of the file, calculated from the hunk headers."
+        },
+        "severity": {
+          "type": "string",
+          "enum": ["blocker", "major", "minor", "info"],
+          "description": "How severe the issue is."
         },
-        "required": [
-          "verdict",
-          "feedback"
-        ],
-        "additionalProperties": false
+        "category": {
+          "type": "string",
+          "enum": categories,
+          "description": "The kind of issue."
+        },
+        "explanation": {
+          "type": "string",
+          "description": "A short markdown explanation of the bug and why it is a bug."
+        },
+        "rule": {
+          "type": "string",
+          "description": "The guideline the code breaks, quoted from the guidelines, or an empty string if the finding isn't a guideline violation."
+        }
       },
+      "required": ["path", "start_line", "end_line", "severity", "category", "explanation", "rule"],
+      "additionalProperties": false
     })
 }
 
-const SYSTEM_PROMPT: &str = "
+static SYSTEM_PROMPT: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "
     <Instructions>
-        Your role is to analyse the code diff and commit messages of pull requests to identify bugs.
-        Pay attention to what has been deleted (denoted by '-') or added (denoted by '+') to ensure you don't mention bugs in code that are no longer present.
+        Your role is to analyse the code diff and commit messages of pull requests to identify bugs and other issues.
+        Pay attention to what has been deleted (denoted by '-') or added (denoted by '+') to ensure you don't mention issues in code that are no longer present.
         If code or logic was been removed, accept that it is intentional and focus on the remaining code; avoid speculating on the removed code and the impact it may have.
-        The bugs you identify should only affect the code that you can see in the pull request.
-        Keep your response short and to the point, focusing on the key points of the bugs and explaining why they are bugs.
-        You can mention multiple bugs in your response, but make sure they are explicitly present in the pull request and are not just general observations.
-        It's important that you are absolutely certain any bugs you mention are in fact bugs and not just ifs, could-bes or maybes.
-        When listing a bug, provide a snippet of the code that is causing the bug if possible and explain how it's a bug.
-        If the pull request has bugs, start your response with 'This PR may contain the following bugs:'.
-        Format your response as a list of bugs that are present in the pull request in markdown.
+        The issues you identify should only affect the code that you can see in the pull request.{CODE_CONTEXT_INSTRUCTIONS}
+        Use the file and symbol context to check how the variables, functions and types used by the changes are defined before raising an issue about them, and whether callers handle changed behaviour, but only add findings for issues in the changed lines.
+        Make sure each issue is explicitly present in the pull request and is not just a general observation.
+        It's important that you are absolutely certain any issues you mention are in fact issues and not just ifs, could-bes or maybes.
+        Add a finding for each issue with the file path and the line range it occurs on in the new version of the file, using the hunk headers (e.g. '@@ -10,7 +12,8 @@' starts at line 12) to count lines.
+        Keep each finding's explanation short and to the point, provide a snippet of the code causing the issue if possible, and explain why it is an issue.
         Double check your output and ensure that it is valid markdown.
-        Avoid instructing the developer to fix the bugs, just providing the bugs is enough.
-        If the pull request does not contain any bugs, simply state 'LGTM 👍'.
-        Your response should be placed in <Output> tags.
+        Avoid instructing the developer to fix the issues, just providing the issues is enough.
+        Give each finding a severity:
+        - blocker: Must be fixed before merging, such as a security vulnerability, data loss or a crash on a common path.
+        - major: Causes incorrect behaviour that users or callers are likely to run into.
+        - minor: Causes incorrect behaviour in uncommon cases, or has a limited impact.
+        - info: Worth knowing about, but unlikely to cause problems.
+        Give each finding a category:
+        - bug: Incorrect logic or behaviour.
+        - security: A vulnerability, such as injection, leaked secrets or missing authorisation.
+        - performance: Unnecessary work, such as repeated queries or quadratic loops over large inputs.
+        - concurrency: Race conditions, deadlocks or unsafe shared state.
+        - error_handling: Errors that are ignored, swallowed or reported incorrectly.
+        If the team has provided guidelines in the Guidelines tags, also treat code in the pull request that breaks a guideline as an issue, and cite the guideline it breaks.
+        Only add findings that are at least as severe as the severity in the MinimumSeverity tags, where blocker is the most severe and info the least.
+        Write a short summary of the issues found, without repeating each finding. Leave the summary empty if there are no issues.
+        If a previous review is given in the PreviousReview tags, the Diff only contains the commits pushed since that review, and the FullDiff tags contain the whole pull request for context.
+        In that case only add findings for new issues introduced by the Diff, and use the FullDiff to check which issues from the previous review have been fixed and which are still present.
     </Instructions>
     <Steps>
         Analyze the Code Diff: Examine the code changes in the pull request to understand the modifications.
         Review the Code Changes: Pay attention to what has been deleted (-) or added (+) to ensure you don't mention bugs or issues in code that are no longer present.
         Analyze Commit Messages: Review the commit messages to gain context and further insights into the changes.
-        Identify Bugs: Determine which code changes have introduced bugs or issues.
-        Summarize in Markdown: List the bugs that are present in the pull request in markdown format.
-        Provide Feedback: Deliver the feedback to the developer.
+        Identify Issues: Determine which code changes have introduced bugs or issues.
+        Classify Findings: Record the file path, line range, severity and category of each issue.
+        Summarize: Write a short markdown summary of the issues for the developer.
     </Steps>
-";
+"
+    )
+});
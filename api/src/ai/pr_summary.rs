@@ -1,53 +1,85 @@
 use anyhow::Result;
 use serde::Deserialize;
 use serde_json::{Value, json};
-use shared::services::{claude, jira::Issue};
+use shared::{
+    services::{
+        github::repo_config::ChangeType,
+        jira::Issue,
+        llm::{self, Llm},
+    },
+    utils::prompt::Prompt,
+};
 
 #[derive(Deserialize)]
 pub struct PrSummary {
     pub summary: String,
+    #[serde(default)]
+    pub change_type: ChangeType,
 }
 
 impl PrSummary {
-    pub async fn new(diff: &str, commit_messages: &[String], issues: &[Issue]) -> Result<Self> {
+    pub async fn new(
+        diff: &str,
+        commit_messages: &[String],
+        issues: &[Issue],
+        guidelines: Option<&str>,
+    ) -> Result<Self> {
         tracing::info!("Generating PR summary");
 
         let commit_messages = commit_messages.join("\n");
-        let issues = issues
-            .iter()
-            .filter(|i| i.fields.description.is_some())
-            .map(|i| {
-                format!(
-                    "- [{}] {}\n{}",
-                    i.key,
-                    i.fields.summary,
-                    i.fields
-                        .description
-                        .as_ref()
-                        .expect("Description to exist because of filter")
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
+        let issues = format_issues(issues);
+        let guidelines = guidelines.unwrap_or_default();
 
-        let user_prompt = format!(
-            "<Diff>{diff}</Diff>
-             <CommitMessages>{commit_messages}</CommitMessages>
-             <JiraIssues>{issues}</JiraIssues>"
-        );
+        let user_prompt = Prompt::new()
+            .untrusted("Diff", diff)
+            .untrusted("CommitMessages", &commit_messages)
+            .untrusted("JiraIssues", &issues)
+            .tag("Guidelines", guidelines)
+            .build();
 
-        claude::Request {
-            user_prompt,
-            system_prompt: SYSTEM_PROMPT,
-            tool_schema: response_schema(),
-            tool_name: "pr_summary",
-            ..Default::default()
-        }
-        .send()
-        .await
+        super::provider()
+            .send(llm::Request {
+                user_prompt,
+                system_prompt: SYSTEM_PROMPT,
+                schema_name: "pr_summary",
+                schema: response_schema(),
+                ..Default::default()
+            })
+            .await
     }
 }
 
+/// Formats the Jira issues that have a description for a prompt.
+pub(super) fn format_issues(issues: &[Issue]) -> String {
+    issues
+        .iter()
+        .filter(|i| i.fields.description.is_some())
+        .map(|i| {
+            format!(
+                "- [{}] {}\n{}",
+                i.key,
+                i.fields.summary,
+                i.fields
+                    .description
+                    .as_ref()
+                    .expect("Description to exist because of filter")
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Schema for the kind of change a pull request makes, which is used to label it.
+pub(super) fn change_type_schema() -> Value {
+    let change_types = ChangeType::ALL.map(|t| t.as_str());
+
+    json!({
+      "type": "string",
+      "enum": change_types,
+      "description": "The kind of change the pull request makes."
+    })
+}
+
 fn response_schema() -> Value {
     let summary = json!({
       "type": "string",
@@ -55,17 +87,16 @@ fn response_schema() -> Value {
     });
 
     json!({
-      "name": "pr_summary",
-      "input_schema": {
-        "type": "object",
-        "properties": {
-          "summary": summary,
-        },
-        "required": [
-          "summary",
-        ],
-        "additionalProperties": false
+      "type": "object",
+      "properties": {
+        "summary": summary,
+        "change_type": change_type_schema(),
       },
+      "required": [
+        "summary",
+        "change_type",
+      ],
+      "additionalProperties": false
     })
 }
 
@@ -77,6 +108,8 @@ const SYSTEM_PROMPT: &str = "
         Use direct language and avoid redundant phrases; the fewer words you use, the clearer your summary will be.
         Avoid including any personal opinions or feedback in your summary, as this is a factual summary of the changes.
         Provide the summary without any introductory or concluding statements.
+        Also classify the kind of change the pull request makes: feature for new behaviour, bugfix for fixing incorrect behaviour, refactor for restructuring code without changing its behaviour, deps for dependency updates, and other for anything else, such as documentation or configuration.
+        If the team has provided guidelines in the Guidelines tags, follow them where they differ from these instructions.
     </Instructions>
     <Steps>
         - Review the diff to understand the changes made in the pull request.
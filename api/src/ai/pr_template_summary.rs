@@ -0,0 +1,135 @@
+use super::pr_summary::{change_type_schema, format_issues};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use shared::{
+    services::{
+        github::{pr_template::Section, repo_config::ChangeType},
+        jira::Issue,
+        llm::{self, Llm},
+    },
+    utils::prompt::{self, Prompt},
+};
+
+/// Content for the sections of a pull request template that the author left empty.
+#[derive(Deserialize)]
+pub struct PrTemplateSummary {
+    sections: Vec<FilledSection>,
+    #[serde(default)]
+    pub change_type: ChangeType,
+}
+
+#[derive(Deserialize)]
+struct FilledSection {
+    heading: String,
+    content: String,
+}
+
+impl PrTemplateSummary {
+    pub async fn new(
+        diff: &str,
+        commit_messages: &[String],
+        issues: &[Issue],
+        guidelines: Option<&str>,
+        sections: &[&Section],
+    ) -> Result<Self> {
+        tracing::info!("Filling {} PR template sections", sections.len());
+
+        let commit_messages = commit_messages.join("\n");
+        let issues = format_issues(issues);
+        let guidelines = guidelines.unwrap_or_default();
+        let sections = sections
+            .iter()
+            .map(|s| {
+                format!(
+                    "<Section><Heading>{}</Heading><Template>{}</Template></Section>",
+                    prompt::escape(s.heading.trim()),
+                    prompt::escape(s.content.trim())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let user_prompt = Prompt::new()
+            .untrusted("Diff", diff)
+            .untrusted("CommitMessages", &commit_messages)
+            .untrusted("JiraIssues", &issues)
+            .tag("Guidelines", guidelines)
+            .raw("Sections", &sections)
+            .build();
+
+        super::provider()
+            .send(llm::Request {
+                user_prompt,
+                system_prompt: SYSTEM_PROMPT,
+                schema_name: "pr_template_summary",
+                schema: response_schema(),
+                ..Default::default()
+            })
+            .await
+    }
+
+    /// Gets the content for each section, keyed by its heading.
+    pub fn get_sections(&self) -> Vec<(String, String)> {
+        self.sections
+            .iter()
+            .filter(|s| !s.content.trim().is_empty())
+            .map(|s| (s.heading.clone(), s.content.clone()))
+            .collect()
+    }
+}
+
+fn response_schema() -> Value {
+    let section = json!({
+      "type": "object",
+      "properties": {
+        "heading": {
+          "type": "string",
+          "description": "The heading of the section exactly as given, including any '#' characters."
+        },
+        "content": {
+          "type": "string",
+          "description": "The markdown content of the section, without the heading."
+        }
+      },
+      "required": ["heading", "content"],
+      "additionalProperties": false
+    });
+
+    json!({
+      "type": "object",
+      "properties": {
+        "sections": {
+          "type": "array",
+          "description": "The content for each of the given sections.",
+          "items": section
+        },
+        "change_type": change_type_schema(),
+      },
+      "required": [
+        "sections",
+        "change_type",
+      ],
+      "additionalProperties": false
+    })
+}
+
+const SYSTEM_PROMPT: &str = "
+    <Instructions>
+        Your task is to fill in the empty sections of a pull request description that follows the team's pull request template.
+        Use the diff, commit messages, and Jira issues (if provided) to write each section given in the Sections tags, following what the heading asks for.
+        Each section includes the template's content for it, which may contain instructions in HTML comments, placeholder text or checklists.
+        Follow any instructions in the template content, and replace placeholder text rather than repeating it.
+        Keep every item of a checklist, and only tick the items that the changes clearly complete.
+        If a section can't be answered from the changes, such as how the changes were tested, write 'N/A' rather than guessing.
+        Keep each section short, clear and concise, using direct language and avoiding personal opinions.
+        Also classify the kind of change the pull request makes: feature for new behaviour, bugfix for fixing incorrect behaviour, refactor for restructuring code without changing its behaviour, deps for dependency updates, and other for anything else, such as documentation or configuration.
+        If the team has provided guidelines in the Guidelines tags, follow them where they differ from these instructions.
+    </Instructions>
+    <Steps>
+        - Review the diff to understand the changes made in the pull request.
+        - Review the commit messages and Jira issues to understand the context of the changes.
+        - Write the content for each section, using its heading and template content to decide what it should contain.
+        - Return each section with its heading exactly as given.
+    </Steps>
+";
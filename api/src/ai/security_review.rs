@@ -0,0 +1,120 @@
+use super::{
+    CODE_CONTEXT_INSTRUCTIONS,
+    pr_review::{CodeContext, Finding, finding_schema},
+};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use shared::{
+    services::{
+        github::repo_config::ReviewConfig,
+        llm::{self, Llm},
+    },
+    utils::prompt::Prompt,
+};
+use std::sync::LazyLock;
+
+/// A review looking only for security issues, run alongside the general review for PRs that
+/// change sensitive code.
+#[derive(Deserialize)]
+pub struct SecurityReview {
+    pub summary: String,
+    pub findings: Vec<Finding>,
+}
+
+impl SecurityReview {
+    pub async fn new(
+        diff: &str,
+        commit_messages: &[String],
+        review_config: &ReviewConfig,
+        guidelines: Option<&str>,
+        code_context: &CodeContext,
+    ) -> Result<Self> {
+        tracing::info!("Generating PR security analysis");
+
+        let commit_messages = commit_messages.join("\n");
+        let guidelines = guidelines.unwrap_or_default();
+
+        let prompt = Prompt::new()
+            .untrusted("Diff", diff)
+            .untrusted("CommitMessages", &commit_messages)
+            .tag("MinimumSeverity", review_config.get_min_severity().as_str())
+            .tag("Guidelines", guidelines);
+
+        let prompt = code_context.add_to_prompt(prompt);
+
+        super::provider()
+            .send(llm::Request {
+                user_prompt: prompt.build(),
+                system_prompt: &SYSTEM_PROMPT,
+                schema_name: "security_review",
+                schema: response_schema(),
+                ..Default::default()
+            })
+            .await
+    }
+}
+
+fn response_schema() -> Value {
+    let summary = json!({
+      "type": "string",
+      "description": "A short markdown overview of the security issues found, without repeating each finding. Empty if there are no issues."
+    });
+
+    let findings = json!({
+      "type": "array",
+      "description": "Each security issue, anchored to the lines it occurs on. Empty if there are no issues.",
+      "items": finding_schema(&["security"])
+    });
+
+    json!({
+      "type": "object",
+      "properties": {
+        "summary": summary,
+        "findings": findings
+      },
+      "required": [
+        "summary",
+        "findings"
+      ],
+      "additionalProperties": false
+    })
+}
+
+static SYSTEM_PROMPT: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "
+    <Instructions>
+        Your role is to review the code diff and commit messages of pull requests for security issues, as an application security engineer would.
+        Look for:
+        - Injection, such as SQL, command, template or path injection from input that isn't escaped or validated.
+        - Authentication and authorisation gaps, such as endpoints or actions that don't check who is calling them or what they can access.
+        - Unsafe deserialization of untrusted input.
+        - Secrets, such as API keys, passwords or private keys, committed to the code or written to logs.
+        - Server-side request forgery, where a request is made to a URL that the caller controls.
+        - Any other vulnerability, such as weak cryptography or sensitive data being exposed.
+        Pay attention to what has been deleted (denoted by '-') or added (denoted by '+'), and only raise issues in code that is present after the pull request.
+        Raise an issue when the diff shows a plausible way to exploit it, even if exploiting it relies on code outside the diff, and explain how it could be exploited.
+        Don't raise general hardening advice or issues that don't relate to the changes.{CODE_CONTEXT_INSTRUCTIONS}
+        Use the file and symbol context to trace input and check for validation or authorisation outside the hunks, but only add findings for issues in the changed lines.
+        Add a finding for each issue with the file path and the line range it occurs on in the new version of the file, using the hunk headers (e.g. '@@ -10,7 +12,8 @@' starts at line 12) to count lines.
+        Keep each finding's explanation short and to the point, provide a snippet of the code causing the issue if possible, and double check it is valid markdown.
+        Give each finding a severity:
+        - blocker: Exploitable by an attacker without special access, or leaks a secret.
+        - major: Exploitable under some conditions, such as by an authenticated user.
+        - minor: Hard to exploit, or only exposes low value data.
+        - info: A risk worth knowing about that isn't exploitable as it is.
+        If the team has provided guidelines in the Guidelines tags, also treat code in the pull request that breaks a security guideline as an issue, and cite the guideline it breaks.
+        Only add findings that are at least as severe as the severity in the MinimumSeverity tags, where blocker is the most severe and info the least.
+        Write a short summary of the security issues found, without repeating each finding. Leave the summary empty if there are no issues.
+    </Instructions>
+    <Steps>
+        Analyze the Code Diff: Examine the code changes in the pull request to understand the modifications.
+        Trace Untrusted Input: Follow input from requests, users and external systems to where it is used.
+        Identify Issues: Determine which code changes have introduced security issues.
+        Classify Findings: Record the file path, line range and severity of each issue.
+        Summarize: Write a short markdown summary of the issues for the developer.
+    </Steps>
+"
+    )
+});
@@ -0,0 +1,77 @@
+use super::Dedupe;
+use anyhow::Result;
+use serde_json::{Value, json};
+use shared::{services::aws, utils::config};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Keeps keys in a DynamoDB table so they're shared between Lambda instances.
+///
+/// The table needs a string partition key named `id`, and TTL enabled on `expires_at` so
+/// expired keys are cleaned up.
+pub struct DynamoDbStore {
+    table_name: String,
+}
+
+impl DynamoDbStore {
+    pub fn new() -> Self {
+        Self {
+            table_name: config::get("DEDUPE_TABLE_NAME"),
+        }
+    }
+}
+
+impl Dedupe for DynamoDbStore {
+    async fn record(&self, key: &str, ttl: Duration) -> Result<bool> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let expires_at = (now + ttl).as_secs();
+
+        // TTL deletion can lag by days, so expired keys are overwritten rather than relied on
+        // having been removed
+        let result = aws::Request {
+            service: "dynamodb",
+            target: "DynamoDB_20120810.PutItem",
+            body: json!({
+                "TableName": self.table_name,
+                "Item": {
+                    "id": { "S": key },
+                    "expires_at": { "N": expires_at.to_string() },
+                },
+                "ConditionExpression": "attribute_not_exists(id) OR expires_at < :now",
+                "ExpressionAttributeValues": {
+                    ":now": { "N": now.as_secs().to_string() },
+                },
+            }),
+        }
+        .send::<Value>()
+        .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(err)
+                if err
+                    .downcast_ref::<aws::Error>()
+                    .is_some_and(|err| err.is("ConditionalCheckFailedException")) =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn forget(&self, key: &str) -> Result<()> {
+        aws::Request {
+            service: "dynamodb",
+            target: "DynamoDB_20120810.DeleteItem",
+            body: json!({
+                "TableName": self.table_name,
+                "Key": {
+                    "id": { "S": key },
+                },
+            }),
+        }
+        .send::<Value>()
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use super::Dedupe;
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Keeps keys for the lifetime of the current process, for the dev server and single instances.
+#[derive(Default)]
+pub struct InMemoryStore {
+    expiries: Mutex<HashMap<String, Instant>>,
+}
+
+impl Dedupe for InMemoryStore {
+    async fn record(&self, key: &str, ttl: Duration) -> Result<bool> {
+        let now = Instant::now();
+        let mut expiries = self.expiries.lock().expect("Dedupe store lock poisoned");
+
+        expiries.retain(|_, expires_at| *expires_at > now);
+
+        if expiries.contains_key(key) {
+            return Ok(false);
+        }
+
+        expiries.insert(key.to_string(), now + ttl);
+
+        Ok(true)
+    }
+
+    async fn forget(&self, key: &str) -> Result<()> {
+        let mut expiries = self.expiries.lock().expect("Dedupe store lock poisoned");
+
+        expiries.remove(key);
+
+        Ok(())
+    }
+}
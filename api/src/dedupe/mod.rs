@@ -0,0 +1,114 @@
+pub mod dynamodb;
+pub mod memory;
+
+use anyhow::Result;
+use dynamodb::DynamoDbStore;
+use memory::InMemoryStore;
+use shared::utils::config;
+use std::{sync::LazyLock, time::Duration};
+
+/// Store shared by webhook extraction and job processing, so it's reachable from the worker too.
+pub static STORE: LazyLock<DedupeStore> = LazyLock::new(DedupeStore::from_config);
+
+const DEFAULT_TTL_HOURS: u64 = 72;
+const DEFAULT_FOLLOW_UP_LIMIT: u64 = 5;
+const FOLLOW_UP_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+pub trait Dedupe {
+    /// Records the key, returning `false` if it was already recorded and hasn't expired.
+    fn record(&self, key: &str, ttl: Duration) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Removes a recorded key, so work that failed after recording it can be retried.
+    fn forget(&self, key: &str) -> impl Future<Output = Result<()>> + Send;
+}
+
+pub enum DedupeStore {
+    InMemory(InMemoryStore),
+    DynamoDb(DynamoDbStore),
+}
+
+impl DedupeStore {
+    /// Uses the store set by `DEDUPE_STORE`, defaulting to one held in memory.
+    pub fn from_config() -> Self {
+        match config::get_optional("DEDUPE_STORE").as_deref() {
+            None | Some("memory") => Self::InMemory(InMemoryStore::default()),
+            Some("dynamodb") => Self::DynamoDb(DynamoDbStore::new()),
+            Some(store) => {
+                panic!("DEDUPE_STORE should be one of 'memory' or 'dynamodb', got '{store}'")
+            }
+        }
+    }
+
+    /// Records a webhook delivery, returning `false` if GitHub has sent it before.
+    pub async fn record_delivery(&self, delivery_id: &str) -> Result<bool> {
+        self.record(&delivery_key(delivery_id), ttl()).await
+    }
+
+    /// Forgets a webhook delivery whose processing failed, so a redelivery isn't skipped.
+    pub async fn forget_delivery(&self, delivery_id: &str) -> Result<()> {
+        self.forget(&delivery_key(delivery_id)).await
+    }
+
+    /// Records a review of a pull request at the given head SHA, returning `false` if that
+    /// commit has already been reviewed.
+    pub async fn record_review(&self, full_name: &str, number: u64, sha: &str) -> Result<bool> {
+        self.record(&review_key(full_name, number, sha), ttl())
+            .await
+    }
+
+    /// Forgets a review that failed, so the commit can be reviewed again.
+    pub async fn forget_review(&self, full_name: &str, number: u64, sha: &str) -> Result<()> {
+        self.forget(&review_key(full_name, number, sha)).await
+    }
+
+    /// Takes one of the PR's follow-up answers for the hour, as limited by `FOLLOW_UP_LIMIT`,
+    /// returning `false` if they've all been used.
+    pub async fn record_follow_up(&self, full_name: &str, number: u64) -> Result<bool> {
+        let limit = config::get_optional("FOLLOW_UP_LIMIT")
+            .and_then(|limit| limit.parse().ok())
+            .unwrap_or(DEFAULT_FOLLOW_UP_LIMIT);
+
+        // Each answer takes the first free slot, which frees up again an hour after it's taken
+        for slot in 0..limit {
+            let key = format!("follow-up:{full_name}#{number}:{slot}");
+
+            if self.record(&key, FOLLOW_UP_WINDOW).await? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl Dedupe for DedupeStore {
+    async fn record(&self, key: &str, ttl: Duration) -> Result<bool> {
+        match self {
+            Self::InMemory(store) => store.record(key, ttl).await,
+            Self::DynamoDb(store) => store.record(key, ttl).await,
+        }
+    }
+
+    async fn forget(&self, key: &str) -> Result<()> {
+        match self {
+            Self::InMemory(store) => store.forget(key).await,
+            Self::DynamoDb(store) => store.forget(key).await,
+        }
+    }
+}
+
+fn delivery_key(delivery_id: &str) -> String {
+    format!("delivery:{delivery_id}")
+}
+
+fn review_key(full_name: &str, number: u64, sha: &str) -> String {
+    format!("review:{full_name}#{number}@{sha}")
+}
+
+fn ttl() -> Duration {
+    let hours = config::get_optional("DEDUPE_TTL_HOURS")
+        .and_then(|hours| hours.parse().ok())
+        .unwrap_or(DEFAULT_TTL_HOURS);
+
+    Duration::from_secs(hours * 60 * 60)
+}
@@ -1,12 +1,15 @@
 mod ai;
+mod dedupe;
 mod middleware;
+mod queue;
 mod routes;
 
 use axum::http::header::{ACCEPT, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_TYPE, ORIGIN};
 use axum::{Router, routing::post};
-use routes::github::pull_request;
+use queue::JobQueue;
+use routes::github::webhook;
 use shared::utils::config;
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 use tower_http::{compression::CompressionLayer, cors::CorsLayer, trace::TraceLayer};
 use tracing::Level;
 use tracing_subscriber::fmt;
@@ -33,11 +36,45 @@ async fn main() {
         .allow_methods(tower_http::cors::Any)
         .allow_origin(tower_http::cors::Any);
 
+    // If compiled in release mode with `WORKER_MODE`, process queued jobs from SQS events
+    // rather than serving webhooks.
+    #[cfg(not(debug_assertions))]
+    if config::get_optional("WORKER_MODE").is_some_and(|m| m == "true") {
+        let handler = lambda_http::service_fn(
+            |event: lambda_http::LambdaEvent<queue::sqs::SqsEvent>| async move {
+                // A malformed record is skipped so it doesn't fail, and retry, the rest of the batch
+                for record in event.payload.records {
+                    match serde_json::from_str(&record.body) {
+                        Ok(job) => routes::github::webhook::process(job).await,
+                        Err(err) => tracing::error!("Error deserialising SQS record: {err}"),
+                    }
+                }
+
+                Ok::<(), lambda_http::Error>(())
+            },
+        );
+
+        lambda_http::lambda_runtime::run(handler).await.unwrap();
+        return;
+    }
+
+    let queue = JobQueue::from_config();
+
+    // Poll SQS from the dev server so the queue can be tested against a local stand-in.
+    #[cfg(debug_assertions)]
+    if let JobQueue::Sqs(_) = queue {
+        tokio::spawn(queue::sqs::SqsQueue::new().poll());
+    }
+
+    // Legacy per-event routes are kept for webhooks configured before the single endpoint.
     let app = Router::new()
-        .route("/github/pull-request/review", post(pull_request::review))
+        .route("/github/webhook", post(webhook::handle))
+        .route("/github/pull-request/review", post(webhook::handle))
+        .route("/github/issue-comment/command", post(webhook::handle))
         .layer(cors_layer)
         .layer(TraceLayer::new_for_http())
-        .layer(CompressionLayer::new().gzip(true).deflate(true));
+        .layer(CompressionLayer::new().gzip(true).deflate(true))
+        .with_state(Arc::new(queue));
 
     // If compiled in debug mode, run app as a regular Axum server.
     #[cfg(debug_assertions)]
@@ -1,3 +1,4 @@
+use crate::{dedupe, queue::Job};
 use axum::{
     body::Bytes,
     extract::{FromRequest, Request},
@@ -9,12 +10,33 @@ use serde::de::DeserializeOwned;
 use shared::utils::config;
 use subtle::ConstantTimeEq;
 
-pub struct GithubEvent<T>(pub T);
+/// A validated webhook whose body is deserialised once its `X-GitHub-Event` type is known.
+pub struct GithubWebhook {
+    pub event: String,
+    pub delivery_id: String,
+    body: Bytes,
+}
+
+impl GithubWebhook {
+    pub fn parse<T>(&self) -> Result<T, (StatusCode, &'static str)>
+    where
+        T: DeserializeOwned,
+    {
+        deseralise_body(&self.body)
+    }
+
+    pub fn into_job(self) -> Job {
+        Job {
+            event: self.event,
+            delivery_id: self.delivery_id,
+            payload: String::from_utf8_lossy(&self.body).into_owned(),
+        }
+    }
+}
 
-impl<T, S> FromRequest<S> for GithubEvent<T>
+impl<S> FromRequest<S> for GithubWebhook
 where
     S: Send + Sync,
-    T: DeserializeOwned,
 {
     type Rejection = (StatusCode, &'static str);
 
@@ -32,9 +54,35 @@ where
             validate_body(signature, &body_as_bytes, token)?;
         }
 
-        let value = deseralise_body(body_as_bytes)?;
+        let get_header = |name| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let delivery_id = get_header("X-GitHub-Delivery");
+
+        // Retried deliveries are acknowledged without being queued again. If the store can't
+        // be reached the delivery is processed anyway rather than dropped.
+        if !delivery_id.is_empty() {
+            match dedupe::STORE.record_delivery(&delivery_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    tracing::info!("Is repeated delivery {delivery_id}, skipping");
+                    return Err(Response::Ok("Delivery already received"));
+                }
+                Err(err) => tracing::error!("Error recording delivery {delivery_id}: {err}"),
+            }
+        }
 
-        Ok(GithubEvent(value))
+        Ok(GithubWebhook {
+            event: get_header("X-GitHub-Event"),
+            delivery_id,
+            body: body_as_bytes,
+        })
     }
 }
 
@@ -72,11 +120,11 @@ async fn convert_body_to_bytes(
     })
 }
 
-fn deseralise_body<T>(body: Bytes) -> Result<T, (StatusCode, &'static str)>
+fn deseralise_body<T>(body: &Bytes) -> Result<T, (StatusCode, &'static str)>
 where
     T: DeserializeOwned,
 {
-    let deserializer = &mut serde_json::Deserializer::from_slice(&body);
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
 
     serde_path_to_error::deserialize(deserializer).map_err(|err| {
         tracing::error!("Error deserialising body: ${err}");
@@ -87,6 +135,11 @@ where
 struct Response;
 
 impl Response {
+    #[allow(non_snake_case)]
+    pub fn Ok(msg: &'static str) -> (StatusCode, &'static str) {
+        (StatusCode::OK, msg)
+    }
+
     #[allow(non_snake_case)]
     pub fn BadRequest(msg: &'static str) -> (StatusCode, &'static str) {
         (StatusCode::BAD_REQUEST, msg)
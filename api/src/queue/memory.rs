@@ -0,0 +1,31 @@
+use super::{Job, Queue};
+use crate::routes::github::webhook;
+use anyhow::Result;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+/// Processes jobs on background tasks of the current process, for the dev server.
+pub struct InMemoryQueue {
+    sender: UnboundedSender<Job>,
+}
+
+impl InMemoryQueue {
+    pub fn start() -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Job>();
+
+        tokio::spawn(async move {
+            while let Some(job) = receiver.recv().await {
+                tokio::spawn(webhook::process(job));
+            }
+        });
+
+        Self { sender }
+    }
+}
+
+impl Queue for InMemoryQueue {
+    async fn enqueue(&self, job: Job) -> Result<()> {
+        self.sender.send(job)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+pub mod memory;
+pub mod sqs;
+
+use anyhow::Result;
+use memory::InMemoryQueue;
+use serde::{Deserialize, Serialize};
+use shared::utils::config;
+use sqs::SqsQueue;
+
+/// A validated webhook delivery waiting to be processed.
+#[derive(Serialize, Deserialize)]
+pub struct Job {
+    pub event: String,
+    pub delivery_id: String,
+    pub payload: String,
+}
+
+pub trait Queue {
+    fn enqueue(&self, job: Job) -> impl Future<Output = Result<()>> + Send;
+}
+
+pub enum JobQueue {
+    InMemory(InMemoryQueue),
+    Sqs(SqsQueue),
+}
+
+impl JobQueue {
+    /// Uses the queue set by `JOB_QUEUE`, defaulting to processing jobs in memory in debug builds.
+    /// Release builds need it set, as a Lambda can be frozen before in-memory jobs finish.
+    pub fn from_config() -> Self {
+        match config::get_optional("JOB_QUEUE").as_deref() {
+            None if !cfg!(debug_assertions) => {
+                panic!("JOB_QUEUE should be set to 'sqs' or 'memory' in release builds")
+            }
+            None | Some("memory") => Self::InMemory(InMemoryQueue::start()),
+            Some("sqs") => Self::Sqs(SqsQueue::new()),
+            Some(queue) => panic!("JOB_QUEUE should be one of 'memory' or 'sqs', got '{queue}'"),
+        }
+    }
+}
+
+impl Queue for JobQueue {
+    async fn enqueue(&self, job: Job) -> Result<()> {
+        tracing::info!("Enqueueing '{}' delivery {}", job.event, job.delivery_id);
+
+        match self {
+            Self::InMemory(queue) => queue.enqueue(job).await,
+            Self::Sqs(queue) => queue.enqueue(job).await,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use super::{Job, Queue};
+#[cfg(debug_assertions)]
+use crate::routes::github::webhook;
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use shared::{services::aws, utils::config};
+
+/// Sends jobs to an SQS queue set by `SQS_QUEUE_URL`, to be processed by the worker Lambda or
+/// by polling against a local stand-in such as ElasticMQ.
+pub struct SqsQueue {
+    queue_url: String,
+}
+
+impl SqsQueue {
+    pub fn new() -> Self {
+        Self {
+            queue_url: config::get("SQS_QUEUE_URL"),
+        }
+    }
+}
+
+/// Polling is only used by the dev server, as the worker Lambda is sent jobs by SQS.
+#[cfg(debug_assertions)]
+impl SqsQueue {
+    /// Long polls the queue and processes jobs until the process exits.
+    pub async fn poll(self) {
+        loop {
+            let messages = match self.receive().await {
+                Ok(messages) => messages,
+                Err(err) => {
+                    tracing::error!("Error receiving SQS messages: {err}");
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+
+            for message in messages {
+                match serde_json::from_str(&message.body) {
+                    Ok(job) => webhook::process(job).await,
+                    Err(err) => tracing::error!("Error deserialising SQS message: {err}"),
+                }
+
+                // Failed jobs aren't retried to avoid posting duplicate comments
+                if let Err(err) = self.delete(&message.receipt_handle).await {
+                    tracing::error!("Error deleting SQS message: {err}");
+                }
+            }
+        }
+    }
+
+    async fn receive(&self) -> Result<Vec<Message>> {
+        let response: ReceiveMessageResponse = aws::Request {
+            service: "sqs",
+            target: "AmazonSQS.ReceiveMessage",
+            body: json!({
+                "QueueUrl": self.queue_url,
+                "MaxNumberOfMessages": 10,
+                "WaitTimeSeconds": 20
+            }),
+        }
+        .send()
+        .await?;
+
+        Ok(response.messages.unwrap_or_default())
+    }
+
+    async fn delete(&self, receipt_handle: &str) -> Result<()> {
+        aws::Request {
+            service: "sqs",
+            target: "AmazonSQS.DeleteMessage",
+            body: json!({
+                "QueueUrl": self.queue_url,
+                "ReceiptHandle": receipt_handle
+            }),
+        }
+        .send::<serde::de::IgnoredAny>()
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl Queue for SqsQueue {
+    async fn enqueue(&self, job: Job) -> Result<()> {
+        aws::Request {
+            service: "sqs",
+            target: "AmazonSQS.SendMessage",
+            body: json!({
+                "QueueUrl": self.queue_url,
+                "MessageBody": serde_json::to_string(&job)?
+            }),
+        }
+        .send::<serde::de::IgnoredAny>()
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(debug_assertions)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ReceiveMessageResponse {
+    messages: Option<Vec<Message>>,
+}
+
+#[cfg(debug_assertions)]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Message {
+    body: String,
+    receipt_handle: String,
+}
+
+/// Event sent to the worker Lambda by an SQS event source mapping.
+#[derive(Deserialize)]
+#[cfg_attr(debug_assertions, allow(dead_code))]
+pub struct SqsEvent {
+    #[serde(rename = "Records")]
+    pub records: Vec<SqsRecord>,
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(debug_assertions, allow(dead_code))]
+pub struct SqsRecord {
+    pub body: String,
+}
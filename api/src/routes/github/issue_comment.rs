@@ -0,0 +1,347 @@
+use super::pull_request::{get_repo_config, review_pr, summarise_pr};
+use crate::{ai, dedupe};
+use anyhow::Result;
+use futures::future::try_join;
+use regex_lite::Regex;
+use serde::Deserialize;
+use shared::{
+    services::github::{
+        Client, Installation, PullRequest, Repository,
+        metadata::{CommentKind, CommentMetadata},
+        pull_request::User,
+    },
+    utils::diff,
+};
+use std::sync::LazyLock;
+
+/// Quotes shorter than this, once normalised, are too vague to tie to a review.
+const MIN_QUOTE_LEN: usize = 20;
+
+/// Matches where a finding is in a review comment, e.g. `` `src/main.rs` (lines 10-12) ``.
+static FINDING_LOCATION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"`([^`]+)` \(lines (\d+)-(\d+)\)").expect("Valid regex"));
+
+pub async fn command(
+    IssueCommentEvent {
+        action,
+        issue,
+        comment,
+        repository: repo,
+        installation,
+    }: IssueCommentEvent,
+) -> Result<()> {
+    if action != "created" || issue.pull_request.is_none() || comment.user.is_bot() {
+        return Ok(());
+    }
+
+    let command = Command::parse(&comment.body);
+    let quote = get_quote(&comment.body);
+
+    if command.is_none() && !is_finding_quote(&quote) {
+        return Ok(());
+    }
+
+    if let Some(command) = &command
+        && !comment.author_association.has_write_access()
+    {
+        tracing::info!(
+            "Commenter on {} #{} doesn't have write access, skipping {command:?} command",
+            repo.name,
+            issue.number
+        );
+        return Ok(());
+    }
+
+    let client = Client::for_webhook(installation.as_ref()).await?;
+
+    let Some(pr) = repo
+        .get_pull_request(&client, &issue.number.to_string())
+        .await?
+    else {
+        tracing::warn!("Pull request #{} not found, skipping", issue.number);
+        return Ok(());
+    };
+
+    let Some(command) = command else {
+        return answer_follow_up(&client, &repo, &pr, &comment.body, &quote).await;
+    };
+
+    tracing::info!(
+        "Processing {command:?} command on {} #{}",
+        repo.name,
+        issue.number
+    );
+
+    if let Command::Ignore = command {
+        pr.ignore(&client).await?;
+        return Ok(());
+    }
+
+    let Some(repo_config) = get_repo_config(&client, &repo, &pr).await? else {
+        return Ok(());
+    };
+
+    let (diff, commit_messages) =
+        try_join(pr.get_diff(&client), pr.get_commit_messages(&client)).await?;
+    let filtered_diff = repo_config.filter_diff(&diff);
+
+    match command {
+        Command::Review => {
+            review_pr(
+                &client,
+                &repo,
+                &repo_config,
+                &pr,
+                &filtered_diff,
+                &commit_messages,
+                false,
+            )
+            .await?
+        }
+        Command::Summarize => {
+            summarise_pr(
+                &client,
+                &repo,
+                &repo_config,
+                &pr,
+                &filtered_diff,
+                &commit_messages,
+            )
+            .await?;
+        }
+        Command::Explain(path) => {
+            explain_file(&client, &pr, &diff, &commit_messages, &path).await?
+        }
+        Command::Ignore => {}
+    }
+
+    Ok(())
+}
+
+/// Answers a comment quoting one of Anno's reviews, as GitHub's "Quote reply" leaves. Issue
+/// comments can't be threaded, so the answer quotes the reply instead.
+async fn answer_follow_up(
+    client: &Client,
+    repo: &Repository,
+    pr: &PullRequest,
+    body: &str,
+    quote: &str,
+) -> Result<()> {
+    let anno_comments = pr.get_anno_comments(client).await?;
+
+    // The model is given Anno's own finding rather than the quote, so a commenter can't write
+    // the finding it judges
+    let Some(finding) = anno_comments
+        .iter()
+        .filter(|c| c.is_review())
+        .find_map(|c| find_quoted_finding(c.get_content(), quote))
+    else {
+        tracing::info!("Doesn't quote a finding from an Anno review, skipping");
+        return Ok(());
+    };
+
+    if pr.is_ignored(client).await? {
+        tracing::info!("Is ignored by an '/anno ignore' command, skipping");
+        return Ok(());
+    }
+
+    if !dedupe::STORE
+        .record_follow_up(&repo.full_name, pr.number)
+        .await?
+    {
+        tracing::info!("Follow-up limit reached for #{}, skipping", pr.number);
+        return Ok(());
+    }
+
+    let reply = body
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('>'))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let location = FINDING_LOCATION_REGEX
+        .captures(&finding)
+        .and_then(|location| Some((location[1].to_string(), location[2].parse().ok()?)));
+
+    let diff_hunk = match location {
+        Some((path, line)) => {
+            let pr_diff = pr.get_diff(client).await?;
+
+            diff::find_hunk(&pr_diff, &path, line).map(str::to_string)
+        }
+        None => None,
+    };
+
+    let follow_up =
+        ai::FollowUp::new(&finding, diff_hunk.as_deref().unwrap_or_default(), &reply).await?;
+
+    let quoted_reply = reply
+        .trim()
+        .lines()
+        .map(|line| format!("> {line}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    pr.add_comment_with_metadata(
+        client,
+        &CommentMetadata::new(CommentKind::FollowUp),
+        &format!("{quoted_reply}\n\n{}", follow_up.answer),
+    )
+    .await
+}
+
+/// Whether a quote looks like it's of one of Anno's findings, going by its text alone so other
+/// quotes are skipped before any API calls are made.
+fn is_finding_quote(quote: &str) -> bool {
+    FINDING_LOCATION_REGEX.is_match(quote) && normalise(quote).len() >= MIN_QUOTE_LEN
+}
+
+/// Finds the finding in a review comment that a quote is taken from. Each finding is listed as
+/// a `- ` item starting with its location, with any further lines indented under it.
+fn find_quoted_finding(review: &str, quote: &str) -> Option<String> {
+    if !is_finding_quote(quote) {
+        return None;
+    }
+
+    let normalised_quote = normalise(quote);
+    let mut findings: Vec<Vec<&str>> = Vec::new();
+    let mut is_in_finding = false;
+
+    for line in review.lines() {
+        if let Some(item) = line.strip_prefix("- ")
+            && FINDING_LOCATION_REGEX.is_match(item)
+        {
+            findings.push(vec![item]);
+            is_in_finding = true;
+        } else if let Some(continuation) = line.strip_prefix("  ")
+            && is_in_finding
+            && let Some(finding) = findings.last_mut()
+        {
+            finding.push(continuation);
+        } else {
+            is_in_finding = false;
+        }
+    }
+
+    findings
+        .into_iter()
+        .map(|lines| lines.join("\n"))
+        .find(|finding| normalise(finding).contains(&normalised_quote))
+}
+
+/// Gets the lines a comment quotes, without their `>`s.
+fn get_quote(body: &str) -> String {
+    body.lines()
+        .filter_map(|line| line.trim_start().strip_prefix('>'))
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Reduces text to lowercase letters and digits, so quotes match the comment they're from even
+/// when the markdown is quoted differently.
+fn normalise(text: &str) -> String {
+    text.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+async fn explain_file(
+    client: &Client,
+    pr: &PullRequest,
+    pr_diff: &str,
+    commit_messages: &[String],
+    path: &str,
+) -> Result<()> {
+    let file_diff = diff::split_by_file(pr_diff)
+        .into_iter()
+        .find(|f| f.path == path || f.path.ends_with(&format!("/{path}")));
+
+    let Some(file_diff) = file_diff else {
+        return pr
+            .add_comment(client, &format!("`{path}` isn't changed in this PR."))
+            .await;
+    };
+
+    let explanation = ai::PrExplanation::new(file_diff.content, commit_messages).await?;
+
+    pr.add_comment(
+        client,
+        &format!(
+            "**Explanation of `{}`**\n\n{}",
+            file_diff.path, explanation.explanation
+        ),
+    )
+    .await
+}
+
+#[derive(Debug)]
+enum Command {
+    Review,
+    Summarize,
+    Explain(String),
+    Ignore,
+}
+
+impl Command {
+    /// Parses the first `/anno <command>` line in a comment body.
+    fn parse(body: &str) -> Option<Self> {
+        let mut args = body
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("/anno "))?
+            .split_whitespace();
+
+        match args.next()? {
+            "review" => Some(Self::Review),
+            "summarize" | "summarise" => Some(Self::Summarize),
+            "explain" => args.next().map(|path| Self::Explain(path.to_string())),
+            "ignore" => Some(Self::Ignore),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct IssueCommentEvent {
+    pub action: String,
+    pub issue: Issue,
+    pub comment: IssueComment,
+    pub repository: Repository,
+    pub installation: Option<Installation>,
+}
+
+#[derive(Deserialize)]
+pub struct Issue {
+    pub number: u64,
+    /// Only present when the issue is a pull request.
+    pub pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+pub struct IssueComment {
+    pub body: String,
+    pub user: User,
+    pub author_association: AuthorAssociation,
+}
+
+/// The commenter's relationship to the repository.
+#[derive(Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuthorAssociation {
+    Owner,
+    Member,
+    Collaborator,
+    #[serde(other)]
+    Other,
+}
+
+impl AuthorAssociation {
+    /// Whether the commenter can push to the repository, so can run commands that cost LLM calls
+    /// or change what Anno posts.
+    pub fn has_write_access(&self) -> bool {
+        matches!(self, Self::Owner | Self::Member | Self::Collaborator)
+    }
+}
@@ -1 +1,6 @@
+pub mod issue_comment;
 pub mod pull_request;
+pub mod pull_request_review_comment;
+pub mod push;
+pub mod release;
+pub mod webhook;
@@ -1,71 +1,290 @@
-use crate::{ai, middleware::validation::GithubEvent};
+use crate::{ai, dedupe};
 use anyhow::Result;
-use futures::future::{try_join, try_join_all};
-use hyper::StatusCode;
+use futures::future::{join_all, try_join, try_join_all, try_join3};
 use regex_lite::Regex;
 use serde::Deserialize;
 use shared::{
     services::{
-        github::{PullRequest, Repository},
+        github::{
+            Client, Installation, PullRequest, RepoConfig, Repository,
+            metadata::{CommentKind, CommentMetadata},
+            pull_request::{DiffPositions, State, remove_summary_section},
+            repo_config::{
+                ChangeType, InvalidRepoConfig, JiraConfig, REVIEW_GUIDELINES_PATH, ReviewOutput,
+                SUMMARY_GUIDELINES_PATH,
+            },
+        },
         jira::Issue,
     },
-    utils::{config, error::AppError},
+    utils::{
+        code_index::{self, CodeIndex, SourceFile},
+        config,
+        diff::{self, FileDiff},
+        file_context,
+    },
 };
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::LazyLock};
+
+/// Pull request actions Anno responds to, some of which can be turned off in `triggers`.
+const PROCESSED_ACTIONS: [&str; 5] = [
+    "opened",
+    "synchronize",
+    "ready_for_review",
+    "reopened",
+    "edited",
+];
+
+const SUMMARY_HEADING: &str = "**Summary**\n\n";
+
+/// Tokens of file context added to reviews unless `REVIEW_CONTEXT_TOKENS` is set.
+const DEFAULT_CONTEXT_TOKENS: usize = 16_000;
+/// Tokens of symbol context added to reviews unless `SYMBOL_CONTEXT_TOKENS` is set.
+const DEFAULT_SYMBOL_CONTEXT_TOKENS: usize = 8_000;
+/// Files from around the changed ones indexed for symbols unless `CODE_INDEX_FILES` is set.
+const DEFAULT_CODE_INDEX_FILES: usize = 1_000;
+/// Most changed files given as context, which limits API calls on large PRs when they have to
+/// be fetched one at a time.
+const MAX_CONTEXT_FILES: usize = 20;
+
+static ISSUE_KEY_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b([A-Z]{2,10})-\d+\b").expect("Valid regex"));
 
 pub async fn review(
-    GithubEvent(PullRequestEvent {
+    PullRequestEvent {
         pull_request: pr,
         repository: repo,
         action,
-    }): GithubEvent<PullRequestEvent>,
-) -> Result<StatusCode, AppError> {
+        changes,
+        installation,
+    }: PullRequestEvent,
+) -> Result<()> {
     tracing::info!("Processing {} pull request #{}", repo.name, pr.title);
 
     if pr.user.is_bot() {
         tracing::info!("Is a bot, skipping");
-        return Ok(StatusCode::OK);
+        return Ok(());
     }
 
-    if action != "opened" && action != "synchronize" {
+    if !PROCESSED_ACTIONS.contains(&action.as_str()) {
         tracing::info!("Is ignored '{action}' action, skipping");
-        return Ok(StatusCode::OK);
+        return Ok(());
+    }
+
+    if pr.state != State::Open {
+        tracing::info!("Is {:?}, skipping", pr.state);
+        return Ok(());
+    }
+
+    // Anno's own description updates are edits too, so only edits mentioning new issues count
+    if action == "edited" && !has_new_issue_keys(&pr, changes.as_ref()) {
+        tracing::info!("Edit doesn't mention new issues, skipping");
+        return Ok(());
+    }
+
+    let client = Client::for_webhook(installation.as_ref()).await?;
+
+    if pr.is_ignored(&client).await? {
+        tracing::info!("Is ignored by an '/anno ignore' command, skipping");
+        return Ok(());
+    }
+
+    let Some(repo_config) = get_repo_config(&client, &repo, &pr).await? else {
+        return Ok(());
+    };
+
+    if pr.draft && !repo_config.triggers.drafts {
+        tracing::info!("Is a draft, skipping");
+        return Ok(());
+    }
+
+    if !repo_config.triggers.is_enabled(&action) {
+        tracing::info!("Is '{action}' action turned off for the repo, skipping");
+        return Ok(());
+    }
+
+    if action == "edited" {
+        return update_issue_links(&client, &repo_config, &pr).await;
+    }
+
+    // Opened, reopened and ready PRs get a full summary and review even if the commit was
+    // reviewed before, such as while it was a draft
+    let is_full_review = action != "synchronize";
+
+    let is_new_commit = dedupe::STORE
+        .record_review(&repo.full_name, pr.number, &pr.head.sha)
+        .await?;
+
+    if !is_new_commit && !is_full_review {
+        tracing::info!("Is already reviewed at {}, skipping", pr.head.sha);
+        return Ok(());
     }
 
-    let diff = pr.get_diff().await?;
-    let commit_messages = pr.get_commit_messages().await?;
+    let result = summarise_and_review(&client, &repo, &repo_config, &pr, is_full_review).await;
 
-    if action == "opened" {
-        let issues = get_jira_issues(&pr).await?;
+    // The commit is recorded up front so concurrent deliveries don't both review it, and
+    // forgotten if the review fails so a retry isn't skipped
+    if result.is_err()
+        && is_new_commit
+        && let Err(err) = dedupe::STORE
+            .forget_review(&repo.full_name, pr.number, &pr.head.sha)
+            .await
+    {
+        tracing::error!("Error forgetting review of {}: {err}", pr.head.sha);
+    }
+
+    result
+}
+
+/// Summarises and reviews the PR's diff, labelling the PR on full reviews.
+async fn summarise_and_review(
+    client: &Client,
+    repo: &Repository,
+    repo_config: &RepoConfig,
+    pr: &PullRequest,
+    is_full_review: bool,
+) -> Result<()> {
+    let diff = repo_config.filter_diff(&pr.get_diff(client).await?);
+
+    if diff.trim().is_empty() {
+        tracing::info!("No changes found for the configured paths, skipping");
+        return Ok(());
+    }
 
-        let (summary, review) = try_join(
-            ai::PrSummary::new(&diff, &commit_messages, &issues),
-            ai::PrReview::new(&diff, &commit_messages),
+    let commit_messages = pr.get_commit_messages(client).await?;
+
+    let refresh_summary =
+        is_full_review || should_refresh_summary(client, repo, repo_config, pr).await;
+
+    let review = review_pr(
+        client,
+        repo,
+        repo_config,
+        pr,
+        &diff,
+        &commit_messages,
+        !is_full_review,
+    );
+
+    // The type of change is only known when the PR is summarised, so its label is otherwise left
+    // as it is, while the size and paths are labelled on every push reviewed
+    let change_type = if refresh_summary {
+        let (change_type, _) = try_join(
+            summarise_pr(client, repo, repo_config, pr, &diff, &commit_messages),
+            review,
         )
         .await?;
 
-        let pr_body = get_pr_body(summary, &pr, &issues);
+        Some(change_type)
+    } else {
+        review.await?;
+        None
+    };
 
-        try_join(pr.set_body(pr_body), pr.add_comment(&review.feedback)).await?;
+    if repo_config.labels.enabled {
+        let changes =
+            repo_config
+                .labels
+                .get_label_changes(&pr.get_label_names(), &diff, change_type);
 
-        return Ok(StatusCode::OK);
+        if !changes.is_empty() {
+            pr.update_labels(client, &changes).await?;
+        }
     }
 
-    let review = ai::PrReview::new(&diff, &commit_messages).await?;
-    let anno_comments = pr.get_anno_comments().await?;
-    let is_prev_positive = anno_comments.first().is_some_and(|c| c.is_positive());
+    Ok(())
+}
 
-    if review.is_positive() && is_prev_positive {
-        return Ok(StatusCode::OK);
+/// Whether enough has changed since the PR was summarised to summarise it again, as set by
+/// `summary.refresh_threshold`.
+async fn should_refresh_summary(
+    client: &Client,
+    repo: &Repository,
+    repo_config: &RepoConfig,
+    pr: &PullRequest,
+) -> bool {
+    let Some(threshold) = repo_config.summary.refresh_threshold else {
+        return false;
+    };
+
+    // PRs without a summary section were summarised before sections existed, or not at all
+    let Some(summarised_sha) = pr.get_summarised_sha() else {
+        return false;
+    };
+
+    if summarised_sha == pr.head.sha {
+        return false;
     }
 
-    try_join(
-        pr.clear_prev_comments(&anno_comments),
-        pr.add_comment(&review.feedback),
-    )
-    .await?;
+    let diff = match repo
+        .get_diff_between_commits(client, summarised_sha, &pr.head.sha)
+        .await
+    {
+        Ok(diff) => repo_config.filter_diff(&diff),
+        Err(err) => {
+            tracing::warn!("Error getting diff since {summarised_sha}, not refreshing: {err}");
+            return false;
+        }
+    };
+
+    let changed_lines = diff::count_changed_lines(&diff);
 
-    Ok(StatusCode::OK)
+    tracing::info!("{changed_lines} lines changed since PR was summarised at {summarised_sha}");
+
+    changed_lines >= threshold
+}
+
+/// Whether the PR's title or description mentions issues that it didn't before it was edited,
+/// ignoring the links in Anno's summary section.
+fn has_new_issue_keys(pr: &PullRequest, changes: Option<&Changes>) -> bool {
+    let Some(changes) = changes else {
+        return false;
+    };
+
+    let prev_title = changes
+        .title
+        .as_ref()
+        .and_then(|c| c.from.as_deref())
+        .unwrap_or(&pr.title);
+    let prev_body = match &changes.body {
+        Some(change) => change.from.as_deref(),
+        None => pr.body.as_deref(),
+    };
+
+    let prev_body = remove_summary_section(prev_body.unwrap_or_default());
+    let body = remove_summary_section(pr.body.as_deref().unwrap_or_default());
+
+    let prev_keys: HashSet<_> = find_issue_keys(prev_title)
+        .chain(find_issue_keys(&prev_body))
+        .collect();
+
+    find_issue_keys(&pr.title)
+        .chain(find_issue_keys(&body))
+        .any(|key| !prev_keys.contains(key))
+}
+
+/// Updates the Jira issue links in Anno's summary section, keeping the summary as it is.
+async fn update_issue_links(
+    client: &Client,
+    repo_config: &RepoConfig,
+    pr: &PullRequest,
+) -> Result<()> {
+    let issues = get_jira_issues(&repo_config.jira, pr).await?;
+
+    if issues.is_empty() {
+        tracing::info!("No Jira issues found, skipping");
+        return Ok(());
+    }
+
+    let summary = pr
+        .get_summary_content()
+        .and_then(|content| content.split_once(SUMMARY_HEADING))
+        .map(|(_, summary)| summary.trim());
+
+    let section = get_summary_section(summary, &issues);
+    let body = pr.body.as_deref().unwrap_or_default();
+
+    pr.set_body(client, pr.get_body_with_updated_summary(body, &section))
+        .await
 }
 
 #[derive(Deserialize)]
@@ -73,30 +292,517 @@ pub struct PullRequestEvent {
     pub action: String,
     pub pull_request: PullRequest,
     pub repository: Repository,
+    /// The previous title and description, for `edited` actions.
+    pub changes: Option<Changes>,
+    pub installation: Option<Installation>,
 }
 
-pub async fn get_jira_issues(pr: &PullRequest) -> Result<Vec<Issue>> {
-    let jira_enabled = config::get_optional("JIRA_API_KEY").is_some();
+#[derive(Deserialize)]
+pub struct Changes {
+    pub title: Option<Change>,
+    pub body: Option<Change>,
+}
 
-    if !jira_enabled {
-        return Ok(Vec::new());
+#[derive(Deserialize)]
+pub struct Change {
+    pub from: Option<String>,
+}
+
+/// Gets the repo's `.anno.yml` config. If it's invalid, the PR is told why it wasn't
+/// processed and `None` is returned.
+pub async fn get_repo_config(
+    client: &Client,
+    repo: &Repository,
+    pr: &PullRequest,
+) -> Result<Option<RepoConfig>> {
+    let err = match repo.get_repo_config(client).await {
+        Ok(repo_config) => return Ok(Some(repo_config)),
+        Err(err) => err.downcast::<InvalidRepoConfig>()?,
+    };
+
+    tracing::warn!("{err}, skipping");
+
+    // Every event on the PR reads the config, so each version of the file is only reported once
+    let is_reported = pr.get_anno_comments(client).await?.iter().any(|c| {
+        c.get_metadata()
+            .is_some_and(|m| m.kind == CommentKind::ConfigError && m.sha.as_deref() == err.sha())
+    });
+
+    if !is_reported {
+        let metadata = CommentMetadata {
+            kind: CommentKind::ConfigError,
+            sha: err.sha().map(str::to_string),
+            ..Default::default()
+        };
+
+        pr.add_comment_with_metadata(
+            client,
+            &metadata,
+            &format!("Anno couldn't process this PR because of a config error.\n\n> {err}"),
+        )
+        .await?;
+    }
+
+    Ok(None)
+}
+
+/// Summarises the PR in its description, returning the kind of change it makes.
+pub async fn summarise_pr(
+    client: &Client,
+    repo: &Repository,
+    repo_config: &RepoConfig,
+    pr: &PullRequest,
+    diff: &str,
+    commit_messages: &[String],
+) -> Result<ChangeType> {
+    let (issues, guidelines, template) = try_join3(
+        get_jira_issues(&repo_config.jira, pr),
+        repo.get_guidelines(client, SUMMARY_GUIDELINES_PATH),
+        repo.get_pr_template(client),
+    )
+    .await?;
+
+    let guidelines = guidelines.as_deref();
+    let body = pr.body.as_deref().unwrap_or_default();
+
+    let unfilled_sections = template
+        .as_ref()
+        .map(|t| t.get_unfilled_sections(body))
+        .unwrap_or_default();
+
+    // Without a template, or when there's nothing in it left to fill because it has no headings
+    // or the author filled them all, the summary goes in Anno's section
+    let Some(template) = template.as_ref().filter(|_| !unfilled_sections.is_empty()) else {
+        let summary = ai::PrSummary::new(diff, commit_messages, &issues, guidelines).await?;
+        let section = get_summary_section(Some(&summary.summary), &issues);
+
+        pr.set_body(client, pr.get_body_with_summary(body, &section))
+            .await?;
+
+        return Ok(summary.change_type);
+    };
+
+    // Otherwise the summary goes in the sections the author hasn't filled in so the description
+    // still follows the template, and only the tickets are added in Anno's section
+    let filled = ai::PrTemplateSummary::new(
+        diff,
+        commit_messages,
+        &issues,
+        guidelines,
+        &unfilled_sections,
+    )
+    .await?;
+
+    let body = template.fill(body, &filled.get_sections());
+    let section = get_summary_section(None, &issues);
+
+    pr.set_body(client, pr.get_body_with_summary(&body, &section))
+        .await?;
+
+    Ok(filled.change_type)
+}
+
+/// Reviews the PR and publishes the result. When `incremental` is set and Anno has reviewed an
+/// earlier commit, only the commits pushed since then are reviewed.
+pub async fn review_pr(
+    client: &Client,
+    repo: &Repository,
+    repo_config: &RepoConfig,
+    pr: &PullRequest,
+    diff: &str,
+    commit_messages: &[String],
+    incremental: bool,
+) -> Result<()> {
+    let guidelines = repo.get_guidelines(client, REVIEW_GUIDELINES_PATH).await?;
+    let guidelines = guidelines.as_deref();
+
+    // Check Runs are kept per commit by GitHub, so there are no previous reviews to clear
+    if repo_config.review.output() == ReviewOutput::CheckRun {
+        let code_context = get_code_context(client, repo, pr, diff).await;
+        let review = get_review(
+            diff,
+            commit_messages,
+            repo_config,
+            guidelines,
+            &code_context,
+            None,
+        )
+        .await?;
+
+        return repo
+            .create_check_run(client, &review.get_check_run(&pr.head.sha))
+            .await;
+    }
+
+    let anno_comments = pr.get_anno_comments(client).await?;
+
+    let reviews = anno_comments.iter().filter(|c| c.is_review());
+
+    let prev_review = reviews
+        .clone()
+        .find_map(|c| Some((c.get_metadata()?.sha.as_deref()?, c.get_content())))
+        .filter(|(sha, _)| incremental && *sha != pr.head.sha);
+
+    let mut review_diff = None;
+
+    if let Some((prev_sha, _)) = prev_review {
+        review_diff = get_diff_since(client, repo, repo_config, pr, prev_sha).await;
+
+        if review_diff.as_ref().is_some_and(|d| d.trim().is_empty()) {
+            tracing::info!("No changes found since {prev_sha} for the configured paths, skipping");
+            return Ok(());
+        }
     }
 
-    let key_regex = Regex::new(r"\b([A-Z]{2,10})-\d+\b").expect("Valid regex");
+    let incremental = match (prev_review, &review_diff) {
+        (Some((prev_sha, prev_review)), Some(_)) => Some(ai::Incremental {
+            prev_sha: &prev_sha[..prev_sha.len().min(7)],
+            prev_review,
+            full_diff: diff,
+        }),
+        _ => None,
+    };
 
-    let mut keys = HashSet::new();
+    let review_diff = review_diff.as_deref().unwrap_or(diff);
+    let code_context = get_code_context(client, repo, pr, review_diff).await;
 
-    if let Some(key) = key_regex.find(&pr.head.r#ref) {
-        keys.insert(key.as_str());
+    let review = get_review(
+        review_diff,
+        commit_messages,
+        repo_config,
+        guidelines,
+        &code_context,
+        incremental.as_ref(),
+    )
+    .await?;
+
+    let is_prev_positive = reviews.clone().next().is_some_and(|c| c.is_positive());
+
+    if review.is_positive() && is_prev_positive {
+        return Ok(());
     }
 
-    if let Some(body) = &pr.body {
-        for key in key_regex.find_iter(body) {
-            keys.insert(key.as_str());
+    // Earlier findings are kept on incremental reviews, as the ones that are still present
+    // aren't posted again, and GitHub marks those on lines that have changed as outdated
+    let clear_prev_findings = async {
+        if incremental.is_some() && !review.is_positive() {
+            return Ok(());
+        }
+
+        pr.clear_prev_findings(client).await
+    };
+
+    try_join3(
+        pr.clear_prev_comments(client, &anno_comments),
+        clear_prev_findings,
+        pr.add_comment_with_metadata(
+            client,
+            &review.get_metadata(&pr.head.sha),
+            &review.get_comment(incremental.as_ref()),
+        ),
+    )
+    .await?;
+
+    let new_diff = incremental.is_some().then_some(review_diff);
+
+    add_review_findings(client, pr, &review, diff, new_diff).await
+}
+
+/// Reviews the diff, running a security review alongside the general one when it's turned on
+/// for the repo or the diff changes sensitive files. Secrets redacted from the diff are added as
+/// findings.
+async fn get_review(
+    diff: &str,
+    commit_messages: &[String],
+    repo_config: &RepoConfig,
+    guidelines: Option<&str>,
+    code_context: &ai::CodeContext,
+    incremental: Option<&ai::Incremental<'_>>,
+) -> Result<ai::PrReview> {
+    let review_config = &repo_config.review;
+
+    let security_review = async {
+        if !review_config.security.should_review(diff) {
+            return Ok(None);
         }
+
+        ai::SecurityReview::new(
+            diff,
+            commit_messages,
+            review_config,
+            guidelines,
+            code_context,
+        )
+        .await
+        .map(Some)
+    };
+
+    let (mut review, security_review) = try_join(
+        ai::PrReview::new(
+            diff,
+            commit_messages,
+            review_config,
+            guidelines,
+            code_context,
+            incremental,
+        ),
+        security_review,
+    )
+    .await?;
+
+    if let Some(security_review) = security_review {
+        review.add_security_review(security_review, review_config);
+    }
+
+    review.add_redaction_findings(diff, review_config);
+
+    Ok(review)
+}
+
+/// Gets code from outside the diff to review it with, as it is at the PR head: the new versions
+/// of the changed files, and the definitions and call sites of the symbols the diff touches in
+/// the files around them. Files that can't be fetched are left out rather than failing the
+/// review.
+async fn get_code_context(
+    client: &Client,
+    repo: &Repository,
+    pr: &PullRequest,
+    diff: &str,
+) -> ai::CodeContext {
+    let file_budget = config::get_number("REVIEW_CONTEXT_TOKENS", DEFAULT_CONTEXT_TOKENS);
+    let symbol_budget = config::get_number("SYMBOL_CONTEXT_TOKENS", DEFAULT_SYMBOL_CONTEXT_TOKENS);
+
+    let changed_files: Vec<_> = diff::split_by_file(diff)
+        .into_iter()
+        .filter(|file| {
+            !file.content.contains("\ndeleted file mode")
+                && !file.content.contains("\nBinary files ")
+        })
+        .take(MAX_CONTEXT_FILES)
+        .collect();
+
+    if changed_files.is_empty() || (file_budget == 0 && symbol_budget == 0) {
+        return ai::CodeContext::default();
     }
 
-    let requests = keys.into_iter().map(Issue::get_by_key).collect::<Vec<_>>();
+    let source_files = get_source_files(client, repo, pr, &changed_files, symbol_budget > 0).await;
+
+    let files = (file_budget > 0)
+        .then(|| get_file_context(&changed_files, &source_files, file_budget))
+        .flatten();
+
+    let symbols = if symbol_budget > 0 {
+        let diff = diff.to_string();
+
+        // Parsing the files is CPU bound, so it's kept off the async runtime's threads
+        tokio::task::spawn_blocking(move || {
+            CodeIndex::new(source_files).get_context(&diff, symbol_budget)
+        })
+        .await
+        .inspect_err(|err| tracing::error!("Error indexing source files: {err}"))
+        .ok()
+        .flatten()
+    } else {
+        None
+    };
+
+    ai::CodeContext { files, symbols }
+}
+
+/// Formats the changed files in diff order until the budget runs out.
+fn get_file_context(
+    changed_files: &[FileDiff<'_>],
+    source_files: &[SourceFile],
+    mut budget: usize,
+) -> Option<String> {
+    let mut context = Vec::new();
+
+    for file in changed_files {
+        if budget == 0 {
+            break;
+        }
+
+        let Some(source_file) = source_files.iter().find(|f| f.path == file.path) else {
+            continue;
+        };
+
+        if let Some(formatted) =
+            file_context::format_file(file.path, &source_file.contents, file.content, budget)
+        {
+            budget = budget.saturating_sub(diff::estimate_tokens(&formatted));
+            context.push(formatted);
+        }
+    }
+
+    (!context.is_empty()).then(|| context.join("\n"))
+}
+
+/// Gets the changed files as they are at the PR head, along with the source files to index
+/// around them when `index` is set. The repo is downloaded as a single tarball, falling back to
+/// fetching just the changed files one at a time if that fails.
+async fn get_source_files(
+    client: &Client,
+    repo: &Repository,
+    pr: &PullRequest,
+    changed_files: &[FileDiff<'_>],
+    index: bool,
+) -> Vec<SourceFile> {
+    let changed_paths: HashSet<String> = changed_files.iter().map(|f| f.path.to_string()).collect();
+
+    let include = move |path: &str, size: u64| {
+        changed_paths.contains(path)
+            || (index
+                && code_index::is_indexable(path)
+                && size as usize <= code_index::MAX_FILE_SIZE)
+    };
+
+    match repo.get_files_at(client, &pr.head.sha, include).await {
+        Ok(files) => get_nearby_files(files, changed_files),
+        Err(err) => {
+            tracing::warn!("Error getting tarball, only fetching changed files: {err}");
+            fetch_files(client, repo, pr, changed_files.iter().map(|f| f.path)).await
+        }
+    }
+}
+
+/// Keeps the changed files and up to `CODE_INDEX_FILES` others, preferring those that share the
+/// most directories with a changed file.
+fn get_nearby_files(files: Vec<SourceFile>, changed_files: &[FileDiff<'_>]) -> Vec<SourceFile> {
+    let limit = config::get_number("CODE_INDEX_FILES", DEFAULT_CODE_INDEX_FILES);
+    let changed_dirs: Vec<_> = changed_files.iter().map(|f| get_dirs(f.path)).collect();
+
+    let (changed, mut others): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|file| changed_files.iter().any(|f| f.path == file.path));
+
+    // Stable, so files equally close stay in tarball order
+    others.sort_by_cached_key(|file| {
+        let dirs = get_dirs(&file.path);
+        let shared_dirs = changed_dirs
+            .iter()
+            .map(|changed| {
+                changed
+                    .iter()
+                    .zip(&dirs)
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            })
+            .max()
+            .unwrap_or_default();
+
+        std::cmp::Reverse(shared_dirs)
+    });
+
+    changed
+        .into_iter()
+        .chain(others.into_iter().take(limit))
+        .collect()
+}
+
+/// Fetches the files as they are at the PR head, leaving out any that can't be fetched.
+async fn fetch_files(
+    client: &Client,
+    repo: &Repository,
+    pr: &PullRequest,
+    paths: impl Iterator<Item = &str>,
+) -> Vec<SourceFile> {
+    let requests = paths.map(|path| async move {
+        let repo_file = repo.find_file_at(client, path, &pr.head.sha).await;
+
+        match repo_file.and_then(|f| f.map(|f| f.decode()).transpose()) {
+            Ok(contents) => contents.map(|contents| SourceFile {
+                path: path.to_string(),
+                contents,
+            }),
+            Err(err) => {
+                tracing::warn!("Error getting {path} for context, skipping: {err}");
+                None
+            }
+        }
+    });
+
+    join_all(requests).await.into_iter().flatten().collect()
+}
+
+fn get_dirs(path: &str) -> Vec<&str> {
+    path.rsplit_once('/')
+        .map_or_else(Vec::new, |(dirs, _)| dirs.split('/').collect())
+}
+
+/// Gets the diff of the commits pushed since the given SHA, or `None` if it can't be compared,
+/// such as when it was removed by a force push, or the branch has merged in its base since, as
+/// the diff would then include the base's changes too.
+async fn get_diff_since(
+    client: &Client,
+    repo: &Repository,
+    repo_config: &RepoConfig,
+    pr: &PullRequest,
+    prev_sha: &str,
+) -> Option<String> {
+    match repo.is_linear_history(client, prev_sha, &pr.head.sha).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::info!(
+                "Branch merged in other commits or was rewritten since {prev_sha}, reviewing whole PR"
+            );
+            return None;
+        }
+        Err(err) => {
+            tracing::warn!("Error comparing with {prev_sha}, reviewing whole PR: {err}");
+            return None;
+        }
+    }
+
+    match repo
+        .get_diff_between_commits(client, prev_sha, &pr.head.sha)
+        .await
+    {
+        Ok(diff) => Some(repo_config.filter_diff(&diff)),
+        Err(err) => {
+            tracing::warn!("Error getting diff since {prev_sha}, reviewing whole PR: {err}");
+            None
+        }
+    }
+}
+
+/// Posts the findings as inline comments, leaving out those outside `new_diff` on incremental
+/// reviews.
+async fn add_review_findings(
+    client: &Client,
+    pr: &PullRequest,
+    review: &ai::PrReview,
+    diff: &str,
+    new_diff: Option<&str>,
+) -> Result<()> {
+    let new_positions = new_diff.map(DiffPositions::new);
+
+    let Some((body, comments)) =
+        review.get_review(&DiffPositions::new(diff), new_positions.as_ref())
+    else {
+        return Ok(());
+    };
+
+    pr.add_review(client, &body, &comments).await
+}
+
+pub async fn get_jira_issues(jira_config: &JiraConfig, pr: &PullRequest) -> Result<Vec<Issue>> {
+    if !jira_config.is_enabled() {
+        return Ok(Vec::new());
+    }
+
+    let body = remove_summary_section(pr.body.as_deref().unwrap_or_default());
+
+    let keys: HashSet<_> = find_issue_keys(&pr.head.r#ref)
+        .take(1)
+        .chain(find_issue_keys(&pr.title))
+        .chain(find_issue_keys(&body))
+        .collect();
+
+    let requests = keys
+        .into_iter()
+        .filter(|key| jira_config.is_issue_included(key))
+        .map(Issue::get_by_key)
+        .collect::<Vec<_>>();
 
     let mut issues: Vec<_> = try_join_all(requests)
         .await?
@@ -109,22 +815,24 @@ pub async fn get_jira_issues(pr: &PullRequest) -> Result<Vec<Issue>> {
     Ok(issues)
 }
 
-pub fn get_pr_body(summary: ai::PrSummary, pr: &PullRequest, issues: &[Issue]) -> String {
-    let mut body = String::new();
+fn find_issue_keys(text: &str) -> impl Iterator<Item = &str> {
+    ISSUE_KEY_REGEX.find_iter(text).map(|key| key.as_str())
+}
 
-    if let Some(existing_body) = &pr.body {
-        body = format!("{existing_body}<hr>\n{body}\n");
-    }
+pub fn get_summary_section(summary: Option<&str>, issues: &[Issue]) -> String {
+    let mut section = String::new();
 
     if !issues.is_empty() {
-        body.push_str("**Tickets**\n");
+        section.push_str("**Tickets**\n");
 
         for issue in issues {
-            body.push_str(&format!("- {}\n", issue.get_github_hyperlink()));
+            section.push_str(&format!("- {}\n", issue.get_github_hyperlink()));
         }
     }
 
-    body.push_str(&format!("**Summary**\n\n{}", summary.summary));
+    if let Some(summary) = summary {
+        section.push_str(&format!("{SUMMARY_HEADING}{summary}"));
+    }
 
-    body
+    section.trim_end().to_string()
 }
@@ -0,0 +1,91 @@
+use crate::{ai, dedupe};
+use anyhow::Result;
+use serde::Deserialize;
+use shared::services::github::{
+    Client, Installation, PullRequest, Repository,
+    metadata::{CommentKind, CommentMetadata},
+    pull_request::InlineComment,
+};
+
+/// Answers replies to the inline comments Anno leaves on its findings, in the same thread.
+pub async fn handle(
+    PullRequestReviewCommentEvent {
+        action,
+        comment,
+        pull_request: pr,
+        repository: repo,
+        installation,
+    }: PullRequestReviewCommentEvent,
+) -> Result<()> {
+    if action != "created" || comment.user.is_bot() {
+        tracing::info!(
+            "Received '{action}' review comment on {} #{}, nothing to do",
+            repo.name,
+            pr.number
+        );
+        return Ok(());
+    }
+
+    let Some(thread_id) = comment.in_reply_to_id else {
+        tracing::info!("Isn't a reply, skipping");
+        return Ok(());
+    };
+
+    let client = Client::for_webhook(installation.as_ref()).await?;
+
+    let Some(finding) = repo.get_inline_comment(&client, thread_id).await? else {
+        tracing::warn!("Inline comment {thread_id} not found, skipping");
+        return Ok(());
+    };
+
+    if !finding.is_finding() {
+        tracing::info!("Isn't a reply to an Anno finding, skipping");
+        return Ok(());
+    }
+
+    if pr.is_ignored(&client).await? {
+        tracing::info!("Is ignored by an '/anno ignore' command, skipping");
+        return Ok(());
+    }
+
+    if !dedupe::STORE
+        .record_follow_up(&repo.full_name, pr.number)
+        .await?
+    {
+        tracing::info!("Follow-up limit reached for #{}, skipping", pr.number);
+        return Ok(());
+    }
+
+    let follow_up =
+        ai::FollowUp::new(finding.get_content(), &comment.diff_hunk, &comment.body).await?;
+
+    pr.reply_to_inline_comment(
+        &client,
+        thread_id,
+        &CommentMetadata::new(CommentKind::FollowUp),
+        &follow_up.answer,
+    )
+    .await?;
+
+    if !follow_up.is_withdrawn() {
+        return Ok(());
+    }
+
+    let withdrawn = format!(
+        "{}_Withdrawn after the discussion below._\n\n<details>\n<summary>Original finding</summary>\n\n{}\n\n</details>",
+        CommentMetadata::new(CommentKind::WithdrawnFinding).to_marker(),
+        finding.get_content().trim()
+    );
+
+    repo.update_inline_comment(&client, thread_id, &withdrawn)
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct PullRequestReviewCommentEvent {
+    pub action: String,
+    pub comment: InlineComment,
+    pub pull_request: PullRequest,
+    pub repository: Repository,
+    pub installation: Option<Installation>,
+}
@@ -0,0 +1,25 @@
+use anyhow::Result;
+use serde::Deserialize;
+use shared::services::github::Repository;
+
+pub async fn handle(
+    PushEvent {
+        r#ref,
+        after,
+        repository: repo,
+    }: PushEvent,
+) -> Result<()> {
+    tracing::info!(
+        "Received push of {after} to {} {ref}, nothing to do",
+        repo.name
+    );
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct PushEvent {
+    pub r#ref: String,
+    pub after: String,
+    pub repository: Repository,
+}
@@ -0,0 +1,31 @@
+use anyhow::Result;
+use serde::Deserialize;
+use shared::services::github::Repository;
+
+pub async fn handle(
+    ReleaseEvent {
+        action,
+        release,
+        repository: repo,
+    }: ReleaseEvent,
+) -> Result<()> {
+    tracing::info!(
+        "Received '{action}' release {} for {}, nothing to do",
+        release.tag_name,
+        repo.name
+    );
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ReleaseEvent {
+    pub action: String,
+    pub release: Release,
+    pub repository: Repository,
+}
+
+#[derive(Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+}
@@ -0,0 +1,98 @@
+use super::{
+    issue_comment::{self, IssueCommentEvent},
+    pull_request::{self, PullRequestEvent},
+    pull_request_review_comment::{self, PullRequestReviewCommentEvent},
+    push::{self, PushEvent},
+    release::{self, ReleaseEvent},
+};
+use crate::{
+    dedupe,
+    middleware::validation::GithubWebhook,
+    queue::{Job, JobQueue, Queue},
+};
+use anyhow::Result;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use hyper::StatusCode;
+use serde::de::DeserializeOwned;
+use shared::utils::error::AppError;
+use std::sync::Arc;
+
+/// Single endpoint for GitHub App webhooks. Deliveries are validated against the payload for
+/// their `X-GitHub-Event` type and queued, so GitHub gets a response well within its timeout.
+pub async fn handle(State(queue): State<Arc<JobQueue>>, webhook: GithubWebhook) -> Response {
+    tracing::info!("Received '{}' webhook", webhook.event);
+
+    let validation = match webhook.event.as_str() {
+        "pull_request" => webhook.parse::<PullRequestEvent>().map(drop),
+        "issue_comment" => webhook.parse::<IssueCommentEvent>().map(drop),
+        "push" => webhook.parse::<PushEvent>().map(drop),
+        "release" => webhook.parse::<ReleaseEvent>().map(drop),
+        "pull_request_review_comment" => webhook.parse::<PullRequestReviewCommentEvent>().map(drop),
+        event => {
+            tracing::info!("Is unhandled '{event}' event, skipping");
+            return StatusCode::NO_CONTENT.into_response();
+        }
+    };
+
+    let delivery_id = webhook.delivery_id.clone();
+
+    if let Err(rejection) = validation {
+        forget_delivery(&delivery_id).await;
+        return rejection.into_response();
+    }
+
+    match queue.enqueue(webhook.into_job()).await {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(err) => {
+            forget_delivery(&delivery_id).await;
+            AppError::from(err).into_response()
+        }
+    }
+}
+
+/// Runs the handler for a queued webhook delivery.
+pub async fn process(job: Job) {
+    tracing::info!("Processing '{}' delivery {}", job.event, job.delivery_id);
+
+    let result = match job.event.as_str() {
+        "pull_request" => dispatch(&job.payload, pull_request::review).await,
+        "issue_comment" => dispatch(&job.payload, issue_comment::command).await,
+        "push" => dispatch(&job.payload, push::handle).await,
+        "release" => dispatch(&job.payload, release::handle).await,
+        "pull_request_review_comment" => {
+            dispatch(&job.payload, pull_request_review_comment::handle).await
+        }
+        event => {
+            tracing::warn!("Is unhandled '{event}' job, skipping");
+            Ok(())
+        }
+    };
+
+    if let Err(err) = result {
+        tracing::error!("Error processing delivery {}: {err}", job.delivery_id);
+        forget_delivery(&job.delivery_id).await;
+    }
+}
+
+/// Lets GitHub's redelivery of a delivery that wasn't processed through, as it's recorded as
+/// soon as it's received.
+async fn forget_delivery(delivery_id: &str) {
+    if delivery_id.is_empty() {
+        return;
+    }
+
+    if let Err(err) = dedupe::STORE.forget_delivery(delivery_id).await {
+        tracing::error!("Error forgetting delivery {delivery_id}: {err}");
+    }
+}
+
+async fn dispatch<T, F>(payload: &str, handler: impl FnOnce(T) -> F) -> Result<()>
+where
+    T: DeserializeOwned,
+    F: Future<Output = Result<()>>,
+{
+    handler(serde_json::from_str(payload)?).await
+}
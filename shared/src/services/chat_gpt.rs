@@ -1,3 +1,4 @@
+use super::llm::{self, Llm};
 use crate::utils::config;
 use anyhow::Result;
 use serde::{de::DeserializeOwned, Deserialize};
@@ -51,6 +52,29 @@ impl Request {
     }
 }
 
+pub struct ChatGpt;
+
+impl Llm for ChatGpt {
+    async fn send<T: DeserializeOwned>(&self, request: llm::Request) -> Result<T> {
+        Request {
+            temperature: request.temperature,
+            system_prompt: request.system_prompt,
+            user_prompt: request.user_prompt,
+            response_schema: json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": request.schema_name,
+                    "schema": request.schema,
+                    "strict": true
+                }
+            }),
+            ..Default::default()
+        }
+        .send()
+        .await
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Response {
     choices: Vec<Choice>,
@@ -1,3 +1,4 @@
+use super::llm::{self, Llm};
 use crate::utils::config;
 use anyhow::Result;
 use serde::{de::DeserializeOwned, Deserialize};
@@ -49,6 +50,26 @@ impl Request {
     }
 }
 
+pub struct Claude;
+
+impl Llm for Claude {
+    async fn send<T: DeserializeOwned>(&self, request: llm::Request) -> Result<T> {
+        Request {
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            system_prompt: request.system_prompt,
+            user_prompt: request.user_prompt,
+            tool_schema: json!({
+                "name": request.schema_name,
+                "input_schema": request.schema
+            }),
+            tool_name: request.schema_name,
+        }
+        .send()
+        .await
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Response<T> {
     pub content: Vec<ContentItem<T>>,
@@ -1,34 +1,56 @@
 use crate::utils::config;
 use anyhow::Result;
 use base64::prelude::*;
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use jsonwebtoken::{Algorithm::RS256, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
-use tokio::sync::OnceCell;
+use std::{collections::HashMap, sync::LazyLock};
+use tokio::sync::Mutex;
 
-#[derive(Deserialize)]
+/// Installation tokens are refreshed this long before they expire so that a token is never
+/// used right at the end of its lifetime.
+const EXPIRY_MARGIN_MINUTES: i64 = 5;
+
+static INSTALLATION_TOKENS: LazyLock<Mutex<HashMap<u64, AccessToken>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Deserialize, Clone)]
 pub struct AccessToken {
     token: String,
+    expires_at: DateTime<Utc>,
 }
 
-pub static GITHUB_ACCESS_TOKEN: OnceCell<String> = OnceCell::const_new();
-
 impl AccessToken {
-    pub async fn get() -> Result<&'static String> {
-        GITHUB_ACCESS_TOKEN.get_or_try_init(Self::fetch).await
+    pub async fn get_for_installation(installation_id: u64) -> Result<String> {
+        if let Some(access_token) = INSTALLATION_TOKENS.lock().await.get(&installation_id)
+            && !access_token.is_expiring()
+        {
+            return Ok(access_token.token.clone());
+        }
+
+        // Fetched without holding the lock so a slow refresh doesn't hold up other installations
+        let access_token = Self::fetch(installation_id).await?;
+        let token = access_token.token.clone();
+
+        INSTALLATION_TOKENS
+            .lock()
+            .await
+            .insert(installation_id, access_token);
+
+        Ok(token)
     }
 
-    async fn fetch() -> Result<String> {
-        // Use token set by Github in an action context if available
-        if let Some(github_token) = config::get_optional("GITHUB_TOKEN") {
-            return Ok(github_token);
-        }
+    fn is_expiring(&self) -> bool {
+        self.expires_at - Duration::minutes(EXPIRY_MARGIN_MINUTES) <= Utc::now()
+    }
+
+    async fn fetch(installation_id: u64) -> Result<Self> {
+        tracing::info!("Fetching GitHub access token for installation {installation_id}");
 
         let gh_base_url = config::get("GITHUB_BASE_URL");
-        let gh_app_install_id = config::get("GITHUB_APP_INSTALLATION_ID");
 
         let jwt_token = create_jwt_token();
-        let url = format!("{gh_base_url}/app/installations/{gh_app_install_id}/access_tokens");
+        let url = format!("{gh_base_url}/app/installations/{installation_id}/access_tokens");
 
         let access_token = reqwest::Client::new()
             .post(url)
@@ -40,8 +62,7 @@ impl AccessToken {
             .error_for_status()
             .inspect_err(|e| tracing::error!("Error fetching GitHub access token: {e}"))?
             .json::<Self>()
-            .await?
-            .token;
+            .await?;
 
         Ok(access_token)
     }
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// A completed Check Run, which branch protection can require to pass.
+pub struct CheckRun {
+    pub name: &'static str,
+    pub head_sha: String,
+    pub conclusion: Conclusion,
+    pub title: String,
+    pub summary: String,
+    pub annotations: Vec<Annotation>,
+}
+
+impl CheckRun {
+    pub(super) fn get_output(&self, annotations: &[Annotation]) -> Value {
+        json!({
+            "title": self.title,
+            "summary": self.summary,
+            "annotations": annotations,
+        })
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Conclusion {
+    Success,
+    Neutral,
+    Failure,
+}
+
+#[derive(Serialize)]
+pub struct Annotation {
+    pub path: String,
+    pub start_line: u64,
+    pub end_line: u64,
+    pub annotation_level: AnnotationLevel,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationLevel {
+    Notice,
+    Warning,
+    Failure,
+}
+
+#[derive(Deserialize)]
+pub(super) struct CreatedCheckRun {
+    pub url: String,
+}
@@ -0,0 +1,50 @@
+use super::AccessToken;
+use crate::utils::config;
+use anyhow::Result;
+use serde::Deserialize;
+
+/// Handle for making authenticated GitHub requests on behalf of a single installation.
+#[derive(Clone)]
+pub struct Client {
+    token: String,
+}
+
+impl Client {
+    pub async fn for_installation(installation_id: u64) -> Result<Self> {
+        let token = AccessToken::get_for_installation(installation_id).await?;
+
+        Ok(Self { token })
+    }
+
+    /// Uses the token set by GitHub in an action context if available, otherwise the app
+    /// installation set by `GITHUB_APP_INSTALLATION_ID`.
+    pub async fn from_env() -> Result<Self> {
+        if let Some(token) = config::get_optional("GITHUB_TOKEN") {
+            return Ok(Self { token });
+        }
+
+        let installation_id = config::get("GITHUB_APP_INSTALLATION_ID")
+            .parse()
+            .expect("GITHUB_APP_INSTALLATION_ID should be a number");
+
+        Self::for_installation(installation_id).await
+    }
+
+    /// Uses the installation that sent a webhook, falling back to the environment for
+    /// deliveries that aren't from a GitHub App.
+    pub async fn for_webhook(installation: Option<&Installation>) -> Result<Self> {
+        match installation {
+            Some(installation) => Self::for_installation(installation.id).await,
+            None => Self::from_env().await,
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Installation {
+    pub id: u64,
+}
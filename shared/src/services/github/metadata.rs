@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+const MARKER_PREFIX: &str = "<!-- anno ";
+const MARKER_SUFFIX: &str = " -->";
+
+/// Markers written before comments carried metadata, which are still recognised on older PRs.
+const LEGACY_MARKER: &str = "<!-- anno -->";
+const LEGACY_IGNORE_MARKER: &str = "<!-- anno:ignore -->";
+
+/// Hidden metadata embedded at the start of Anno's comments as `<!-- anno {json} -->`, so they
+/// can be identified and classified without matching on their wording.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct CommentMetadata {
+    pub kind: CommentKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<Verdict>,
+    /// Head SHA a review was written for, or blob SHA of the `.anno.yml` a config error is about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommentKind {
+    Review,
+    Ignore,
+    /// An inline comment on one of a review's findings.
+    Finding,
+    /// A finding Anno withdrew after a reply, which isn't answered again.
+    WithdrawnFinding,
+    /// A finding from an earlier review, hidden when a newer review was posted.
+    OutdatedFinding,
+    /// An answer to a reply on one of Anno's comments.
+    FollowUp,
+    /// An error reading the repo's `.anno.yml`.
+    ConfigError,
+    /// Any other comment, such as an explanation or error.
+    #[default]
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Positive,
+    Negative,
+}
+
+impl CommentMetadata {
+    pub fn new(kind: CommentKind) -> Self {
+        Self {
+            kind,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_marker(&self) -> String {
+        let json = serde_json::to_string(self).expect("Metadata to serialise");
+
+        // `--` would end the HTML comment early
+        format!(
+            "{MARKER_PREFIX}{}{MARKER_SUFFIX}",
+            json.replace("--", "\\u002d\\u002d")
+        )
+    }
+
+    /// Parses the metadata from the start of a comment body, returning `None` if the comment
+    /// wasn't written by Anno.
+    pub fn parse(body: &str) -> Option<Self> {
+        if let Some(legacy_body) = body.strip_prefix(LEGACY_MARKER) {
+            return Some(Self::from_legacy(legacy_body));
+        }
+
+        let (json, _) = body
+            .strip_prefix(MARKER_PREFIX)?
+            .split_once(MARKER_SUFFIX)?;
+
+        serde_json::from_str(json)
+            .inspect_err(|e| tracing::warn!("Error parsing Anno comment metadata: {e}"))
+            .ok()
+    }
+
+    /// Strips the marker from a comment body.
+    pub fn strip(body: &str) -> &str {
+        if let Some(legacy_body) = body.strip_prefix(LEGACY_MARKER) {
+            return legacy_body;
+        }
+
+        body.strip_prefix(MARKER_PREFIX)
+            .and_then(|rest| rest.split_once(MARKER_SUFFIX))
+            .map_or(body, |(_, content)| content)
+    }
+
+    fn from_legacy(body: &str) -> Self {
+        if body.contains(LEGACY_IGNORE_MARKER) {
+            return Self::new(CommentKind::Ignore);
+        }
+
+        let verdict = if body.contains("LGTM") {
+            Verdict::Positive
+        } else {
+            Verdict::Negative
+        };
+
+        Self {
+            kind: CommentKind::Review,
+            verdict: Some(verdict),
+            ..Default::default()
+        }
+    }
+}
@@ -1,9 +1,16 @@
 pub mod access_token;
+pub mod check_run;
+pub mod client;
+pub mod metadata;
+pub mod pr_template;
 pub mod pull_request;
+pub mod repo_config;
 pub mod repository;
 
 pub use access_token::AccessToken;
+pub use client::{Client, Installation};
 pub use pull_request::PullRequest;
+pub use repo_config::RepoConfig;
 pub use repository::Repository;
 
 pub const IGNORED_REPO_PATHS: [&str; 9] = [
@@ -0,0 +1,162 @@
+use super::pull_request::find_summary_section;
+use regex_lite::Regex;
+use std::sync::LazyLock;
+
+/// Locations GitHub looks for a pull request template, in order.
+pub const PR_TEMPLATE_PATHS: [&str; 4] = [
+    ".github/pull_request_template.md",
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    "pull_request_template.md",
+    "docs/pull_request_template.md",
+];
+
+static HEADING_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#{1,6}\s+\S").expect("Valid regex"));
+
+/// A pull request template split into the sections under each of its headings.
+pub struct PrTemplate {
+    sections: Vec<Section>,
+}
+
+pub struct Section {
+    /// The heading line, including its `#`s.
+    pub heading: String,
+    pub content: String,
+}
+
+impl Section {
+    fn get_title(&self) -> String {
+        get_heading_title(&self.heading)
+    }
+}
+
+impl PrTemplate {
+    pub fn parse(template: &str) -> Self {
+        let (_, sections) = split_sections(template);
+
+        Self { sections }
+    }
+
+    /// Gets the template sections that are missing from the description, or which the author
+    /// left empty or unchanged from the template.
+    pub fn get_unfilled_sections(&self, body: &str) -> Vec<&Section> {
+        let (_, body_sections) = split_sections(body);
+
+        self.sections
+            .iter()
+            .filter(|section| {
+                let body_section = body_sections
+                    .iter()
+                    .find(|s| s.get_title() == section.get_title());
+
+                let Some(body_section) = body_section else {
+                    return true;
+                };
+
+                let content = strip_comments(&body_section.content);
+
+                content.is_empty() || content == strip_comments(&section.content)
+            })
+            .collect()
+    }
+
+    /// Writes the filled content of each heading into the description, leaving every other part
+    /// of it as it was. Sections missing from the description are added at the end.
+    pub fn fill(&self, body: &str, filled: &[(String, String)]) -> String {
+        let (preamble, mut body_sections) = split_sections(body);
+
+        for (heading, content) in filled {
+            let title = get_heading_title(heading);
+
+            let Some(section) = self.sections.iter().find(|s| s.get_title() == title) else {
+                continue;
+            };
+
+            match body_sections.iter_mut().find(|s| s.get_title() == title) {
+                Some(body_section) => {
+                    // Keep Anno's summary section if it was added after this section's content
+                    let summary_section = find_summary_section(&body_section.content)
+                        .map(|(start, end)| body_section.content[start..end].to_string());
+
+                    body_section.content = format!("\n{}\n\n", content.trim());
+
+                    if let Some(summary_section) = summary_section {
+                        body_section
+                            .content
+                            .push_str(&format!("{summary_section}\n"));
+                    }
+                }
+                None => body_sections.push(Section {
+                    heading: format!("{}\n", section.heading.trim_end()),
+                    content: format!("\n{}\n\n", content.trim()),
+                }),
+            }
+        }
+
+        let mut body = preamble;
+
+        for section in body_sections {
+            if !body.is_empty() && !body.ends_with('\n') {
+                body.push('\n');
+            }
+
+            body.push_str(&section.heading);
+            body.push_str(&section.content);
+        }
+
+        body
+    }
+}
+
+/// Splits markdown into the text before the first heading and the sections under each heading,
+/// keeping the original text so it can be put back together unchanged.
+fn split_sections(markdown: &str) -> (String, Vec<Section>) {
+    let mut preamble = String::new();
+    let mut sections: Vec<Section> = Vec::new();
+    let mut is_in_code_block = false;
+
+    for line in markdown.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            is_in_code_block = !is_in_code_block;
+        }
+
+        if !is_in_code_block && HEADING_REGEX.is_match(line) {
+            sections.push(Section {
+                heading: line.to_string(),
+                content: String::new(),
+            });
+            continue;
+        }
+
+        match sections.last_mut() {
+            Some(section) => section.content.push_str(line),
+            None => preamble.push_str(line),
+        }
+    }
+
+    (preamble, sections)
+}
+
+/// Gets a heading's text without its `#`s, for matching headings regardless of their level.
+fn get_heading_title(heading: &str) -> String {
+    heading.trim().trim_start_matches('#').trim().to_lowercase()
+}
+
+/// Removes HTML comments and Anno's summary section, which aren't the author's content.
+fn strip_comments(content: &str) -> String {
+    let mut content = content.to_string();
+
+    if let Some((start, end)) = find_summary_section(&content) {
+        content.replace_range(start..end, "");
+    }
+
+    while let Some(start) = content.find("<!--") {
+        let Some(end) = content[start..].find("-->") else {
+            break;
+        };
+
+        content.replace_range(start..start + end + 3, "");
+    }
+
+    content.trim().to_string()
+}
@@ -1,9 +1,17 @@
-use super::{repository::Commit, AccessToken, IGNORED_REPO_PATHS};
-use crate::utils::config;
+use super::{
+    metadata::{CommentKind, CommentMetadata, Verdict},
+    repo_config::LabelChanges,
+    repository::Commit,
+    Client, IGNORED_REPO_PATHS,
+};
+use crate::utils::{config, diff, redact};
 use anyhow::Result;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::cmp::Reverse;
+use std::{cmp::Reverse, collections::HashMap};
+
+const SUMMARY_START_MARKER: &str = "<!-- anno:summary-start";
+const SUMMARY_END_MARKER: &str = "<!-- anno:summary-end -->";
 
 #[derive(Deserialize)]
 pub struct PullRequest {
@@ -12,17 +20,24 @@ pub struct PullRequest {
     pub html_url: String,
     pub body: Option<String>,
     pub user: User,
-    pub head: Head,
+    #[serde(default)]
+    pub draft: bool,
+    pub state: State,
+    pub head: Branch,
+    pub base: Branch,
+    #[serde(default)]
+    pub labels: Vec<Label>,
     url: String,
+    issue_url: String,
     comments_url: String,
     commits_url: String,
 }
 
 impl PullRequest {
-    pub async fn set_body(&self, body: String) -> Result<()> {
+    pub async fn set_body(&self, client: &Client, body: String) -> Result<()> {
         tracing::info!("Setting pull request #{} body", &self.number);
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
 
         reqwest::Client::new()
             .patch(&self.url)
@@ -38,10 +53,107 @@ impl PullRequest {
         Ok(())
     }
 
-    pub async fn get_diff(&self) -> Result<String> {
+    /// Adds and removes labels without touching the PR's others, creating any the repo doesn't
+    /// have yet.
+    pub async fn update_labels(&self, client: &Client, changes: &LabelChanges) -> Result<()> {
+        tracing::info!("Updating pull request #{} labels", &self.number);
+
+        let pr_comment_enabled = config::get("PR_COMMENT_ENABLED") == "true";
+
+        if !pr_comment_enabled {
+            println!(
+                "Labels added: {}, removed: {}",
+                changes.added.join(", "),
+                changes.removed.join(", ")
+            );
+            return Ok(());
+        }
+
+        let gh_token = client.token();
+        let labels_url = format!("{}/labels", self.issue_url);
+
+        for label in &changes.removed {
+            let mut url = reqwest::Url::parse(&labels_url)?;
+            url.path_segments_mut()
+                .map_err(|_| anyhow::anyhow!("Invalid labels URL {labels_url}"))?
+                .push(label);
+
+            // A 404 means someone else removed it first
+            match reqwest::Client::new()
+                .delete(url)
+                .bearer_auth(gh_token)
+                .header("Accept", "application/json")
+                .header("User-Agent", "Anno")
+                .send()
+                .await?
+                .error_for_status()
+            {
+                Ok(_) => {}
+                Err(err) if err.status() == Some(reqwest::StatusCode::NOT_FOUND) => {}
+                Err(err) => {
+                    tracing::error!("Error removing PR label: {err}");
+                    return Err(err.into());
+                }
+            }
+        }
+
+        if changes.added.is_empty() {
+            return Ok(());
+        }
+
+        reqwest::Client::new()
+            .post(labels_url)
+            .bearer_auth(gh_token)
+            .header("Accept", "application/json")
+            .header("User-Agent", "Anno")
+            .json(&json!({ "labels": changes.added }))
+            .send()
+            .await?
+            .error_for_status()
+            .inspect_err(|e| tracing::error!("Error adding PR labels: {e}"))?;
+
+        Ok(())
+    }
+
+    pub fn get_label_names(&self) -> Vec<String> {
+        self.labels.iter().map(|l| l.name.clone()).collect()
+    }
+
+    /// Gets the head SHA the summary section of the description was written for.
+    pub fn get_summarised_sha(&self) -> Option<&str> {
+        let (_, marker) = self.body.as_deref()?.split_once(SUMMARY_START_MARKER)?;
+        let (attributes, _) = marker.split_once("-->")?;
+
+        attributes.trim().strip_prefix("sha=")
+    }
+
+    /// Gets the content of Anno's summary section in the description.
+    pub fn get_summary_content(&self) -> Option<&str> {
+        let body = self.body.as_deref()?;
+        let (start, end) = find_summary_section(body)?;
+        let (_, content) = body[start..end - SUMMARY_END_MARKER.len()].split_once("-->")?;
+
+        Some(content.trim_start().trim_start_matches("<hr>").trim())
+    }
+
+    /// Gets the description with Anno's summary section replaced in place, or added after the
+    /// author's text if it doesn't have one yet.
+    pub fn get_body_with_summary(&self, body: &str, summary: &str) -> String {
+        replace_summary_section(body, summary, &self.head.sha)
+    }
+
+    /// Gets the description with the content of Anno's summary section updated, keeping the
+    /// commit it was summarised at so refreshes still count changes from there.
+    pub fn get_body_with_updated_summary(&self, body: &str, summary: &str) -> String {
+        let sha = self.get_summarised_sha().unwrap_or(&self.head.sha);
+
+        replace_summary_section(body, summary, sha)
+    }
+
+    pub async fn get_diff(&self, client: &Client) -> Result<String> {
         tracing::info!("Fetching pull request #{} diff", &self.number);
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
 
         let diff = reqwest::Client::new()
             .get(&self.url)
@@ -69,13 +181,13 @@ impl PullRequest {
             .collect::<Vec<_>>()
             .join("\n");
 
-        Ok(filtered_diff)
+        Ok(redact::redact_diff(&filtered_diff))
     }
 
-    pub async fn get_commit_messages(&self) -> Result<Vec<String>> {
+    pub async fn get_commit_messages(&self, client: &Client) -> Result<Vec<String>> {
         tracing::info!("Fetching pull request #{} commit messages", &self.number);
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
 
         let mut all_commits: Vec<Commit> = Vec::new();
         let mut page = 1;
@@ -107,12 +219,12 @@ impl PullRequest {
         Ok(all_messages)
     }
 
-    pub async fn get_anno_comments(&self) -> Result<Vec<Comment>> {
+    pub async fn get_anno_comments(&self, client: &Client) -> Result<Vec<Comment>> {
         let mut comments: Vec<_> = self
-            .list_comments()
+            .list_comments(client)
             .await?
             .into_iter()
-            .filter(|c| c.is_by_anno())
+            .filter(|c| c.is_by_anno() && !c.is_ignore_marker())
             .collect();
 
         comments.sort_by_key(|c| Reverse(c.created_at.clone()));
@@ -120,8 +232,39 @@ impl PullRequest {
         Ok(comments)
     }
 
-    pub async fn add_comment(&self, comment: &str) -> Result<()> {
-        tracing::info!("Adding pull request #{} comment", &self.number);
+    pub async fn is_ignored(&self, client: &Client) -> Result<bool> {
+        let comments = self.list_comments(client).await?;
+
+        Ok(comments.iter().any(|c| c.is_ignore_marker()))
+    }
+
+    pub async fn ignore(&self, client: &Client) -> Result<()> {
+        tracing::info!("Ignoring pull request #{}", &self.number);
+
+        self.add_comment_with_metadata(
+            client,
+            &CommentMetadata::new(CommentKind::Ignore),
+            "Anno will no longer review this PR automatically. Comment `/anno review` to request a review.",
+        )
+        .await
+    }
+
+    pub async fn add_comment(&self, client: &Client, comment: &str) -> Result<()> {
+        self.add_comment_with_metadata(client, &CommentMetadata::default(), comment)
+            .await
+    }
+
+    pub async fn add_comment_with_metadata(
+        &self,
+        client: &Client,
+        metadata: &CommentMetadata,
+        comment: &str,
+    ) -> Result<()> {
+        tracing::info!(
+            "Adding pull request #{} {:?} comment",
+            &self.number,
+            metadata.kind
+        );
 
         let pr_comment_enabled = config::get("PR_COMMENT_ENABLED") == "true";
 
@@ -130,14 +273,14 @@ impl PullRequest {
             return Ok(());
         }
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
 
         reqwest::Client::new()
             .post(&self.comments_url)
             .bearer_auth(gh_token)
             .header("Accept", "application/json")
             .header("User-Agent", "Anno")
-            .json(&json!({ "body": format!("<!-- anno -->{comment}") }))
+            .json(&json!({ "body": format!("{}{comment}", metadata.to_marker()) }))
             .send()
             .await?
             .error_for_status()
@@ -146,107 +289,442 @@ impl PullRequest {
         Ok(())
     }
 
-    pub async fn clear_prev_comments(&self, comments: &[Comment]) -> Result<()> {
+    pub async fn add_review(
+        &self,
+        client: &Client,
+        body: &str,
+        comments: &[ReviewComment],
+    ) -> Result<()> {
+        tracing::info!(
+            "Adding pull request #{} review with {} comments",
+            &self.number,
+            comments.len()
+        );
+
         let pr_comment_enabled = config::get("PR_COMMENT_ENABLED") == "true";
 
         if !pr_comment_enabled {
+            println!("{body}");
+            for comment in comments {
+                println!("{}:{} {}", comment.path, comment.position, comment.body);
+            }
             return Ok(());
         }
 
-        let (positives, negatives) = comments.iter().partition::<Vec<_>, _>(|c| c.is_positive());
+        let gh_token = client.token();
 
-        if let Some(prev_positive) = positives.first() {
-            prev_positive.delete().await?;
-        }
+        // Marked so replies to a finding can be told apart from replies to other comments
+        let finding_marker = CommentMetadata::new(CommentKind::Finding).to_marker();
+        let comments = comments
+            .iter()
+            .map(|comment| ReviewComment {
+                path: comment.path.clone(),
+                position: comment.position,
+                body: format!("{finding_marker}{}", comment.body),
+            })
+            .collect::<Vec<_>>();
 
-        if let Some(prev_negative) = negatives.first() {
-            prev_negative.hide_as_outdated().await?;
-        }
+        reqwest::Client::new()
+            .post(format!("{}/reviews", self.url))
+            .bearer_auth(gh_token)
+            .header("Accept", "application/json")
+            .header("User-Agent", "Anno")
+            .json(&json!({
+                "commit_id": self.head.sha,
+                "body": format!("{}{body}", CommentMetadata::default().to_marker()),
+                "event": "COMMENT",
+                "comments": comments
+            }))
+            .send()
+            .await?
+            .error_for_status()
+            .inspect_err(|e| tracing::error!("Error adding GitHub review: {e}"))?;
 
         Ok(())
     }
 
-    async fn list_comments(&self) -> Result<Vec<Comment>> {
-        tracing::info!("Getting pull request #{} comments", &self.number);
+    /// Replies in the thread of an inline comment.
+    pub async fn reply_to_inline_comment(
+        &self,
+        client: &Client,
+        comment_id: u64,
+        metadata: &CommentMetadata,
+        reply: &str,
+    ) -> Result<()> {
+        tracing::info!(
+            "Replying to pull request #{} inline comment {comment_id}",
+            &self.number
+        );
+
+        let pr_comment_enabled = config::get("PR_COMMENT_ENABLED") == "true";
 
-        let gh_token = AccessToken::get().await?;
+        if !pr_comment_enabled {
+            println!("{reply}");
+            return Ok(());
+        }
 
-        let comments = reqwest::Client::new()
-            .get(&self.comments_url)
+        let gh_token = client.token();
+
+        reqwest::Client::new()
+            .post(format!("{}/comments/{comment_id}/replies", self.url))
             .bearer_auth(gh_token)
             .header("Accept", "application/json")
             .header("User-Agent", "Anno")
-            .query(&[("per_page", "100")])
+            .json(&json!({ "body": format!("{}{reply}", metadata.to_marker()) }))
             .send()
             .await?
             .error_for_status()
-            .inspect_err(|e| tracing::error!("Error getting GitHub comments: {e}"))?
-            .json::<Vec<Comment>>()
-            .await?;
+            .inspect_err(|e| tracing::error!("Error replying to inline comment: {e}"))?;
 
-        Ok(comments)
+        Ok(())
+    }
+
+    pub async fn clear_prev_comments(&self, client: &Client, comments: &[Comment]) -> Result<()> {
+        let pr_comment_enabled = config::get("PR_COMMENT_ENABLED") == "true";
+
+        if !pr_comment_enabled {
+            return Ok(());
+        }
+
+        let (positives, negatives) = comments
+            .iter()
+            .filter(|c| c.is_review())
+            .partition::<Vec<_>, _>(|c| c.is_positive());
+
+        if let Some(prev_positive) = positives.first() {
+            prev_positive.delete(client).await?;
+        }
+
+        if let Some(prev_negative) = negatives.first() {
+            prev_negative.hide_as_outdated(client).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Hides the inline comments of earlier reviews' findings as outdated, as they're replaced by
+    /// the findings of the latest review.
+    pub async fn clear_prev_findings(&self, client: &Client) -> Result<()> {
+        let pr_comment_enabled = config::get("PR_COMMENT_ENABLED") == "true";
+
+        if !pr_comment_enabled {
+            return Ok(());
+        }
+
+        let findings = self
+            .list_inline_comments(client)
+            .await?
+            .into_iter()
+            .filter(InlineComment::is_finding);
+
+        for finding in findings {
+            finding.mark_outdated(client).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn list_comments(&self, client: &Client) -> Result<Vec<Comment>> {
+        tracing::info!("Getting pull request #{} comments", &self.number);
+
+        let gh_token = client.token();
+
+        let mut all_comments: Vec<Comment> = Vec::new();
+        let mut page = 1;
+        loop {
+            let comments: Vec<Comment> = reqwest::Client::new()
+                .get(&self.comments_url)
+                .bearer_auth(gh_token)
+                .header("Accept", "application/json")
+                .header("User-Agent", "Anno")
+                .query(&[("page", page), ("per_page", 100)])
+                .send()
+                .await?
+                .error_for_status()
+                .inspect_err(|e| tracing::error!("Error getting GitHub comments: {e}"))?
+                .json()
+                .await?;
+
+            if comments.is_empty() {
+                break;
+            }
+
+            all_comments.extend(comments);
+
+            page += 1;
+        }
+
+        // Comments by anyone else are left without metadata even if they copy Anno's marker
+        for comment in all_comments
+            .iter_mut()
+            .filter(|c| c.user.is_anno(c.performed_via_github_app.as_ref()))
+        {
+            comment.metadata = CommentMetadata::parse(&comment.body);
+        }
+
+        Ok(all_comments)
+    }
+
+    async fn list_inline_comments(&self, client: &Client) -> Result<Vec<InlineComment>> {
+        tracing::info!("Getting pull request #{} inline comments", &self.number);
+
+        let gh_token = client.token();
+
+        let mut all_comments: Vec<InlineComment> = Vec::new();
+        let mut page = 1;
+        loop {
+            let comments: Vec<InlineComment> = reqwest::Client::new()
+                .get(format!("{}/comments", self.url))
+                .bearer_auth(gh_token)
+                .header("Accept", "application/json")
+                .header("User-Agent", "Anno")
+                .query(&[("page", page), ("per_page", 100)])
+                .send()
+                .await?
+                .error_for_status()
+                .inspect_err(|e| tracing::error!("Error getting GitHub inline comments: {e}"))?
+                .json()
+                .await?;
+
+            if comments.is_empty() {
+                break;
+            }
+
+            all_comments.extend(comments);
+
+            page += 1;
+        }
+
+        Ok(all_comments)
     }
 }
 
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Open,
+    Closed,
+}
+
+#[derive(Deserialize)]
+pub struct Label {
+    pub name: String,
+}
+
 #[derive(Deserialize)]
-pub struct Head {
+pub struct Branch {
     pub r#ref: String,
+    pub sha: String,
+}
+
+#[derive(Serialize)]
+pub struct ReviewComment {
+    pub path: String,
+    pub position: u64,
+    pub body: String,
+}
+
+/// Maps lines in the new version of each file to their position in the PR diff, which is what
+/// GitHub uses to anchor review comments. Only added and unchanged lines within a hunk can be
+/// commented on.
+pub struct DiffPositions(HashMap<String, HashMap<u64, u64>>);
+
+impl DiffPositions {
+    pub fn new(pr_diff: &str) -> Self {
+        let mut files = HashMap::new();
+
+        for file in diff::split_by_file(pr_diff) {
+            let mut lines = HashMap::new();
+            let mut position: Option<u64> = None;
+            let mut new_line = 0;
+
+            for line in file.content.lines() {
+                if line.starts_with("@@") {
+                    // The first hunk header is position 0, subsequent ones count as diff lines
+                    position = Some(position.map_or(0, |p| p + 1));
+                    new_line = diff::parse_hunk_new_start(line).unwrap_or(0);
+                    continue;
+                }
+
+                let Some(position) = position.as_mut() else {
+                    continue;
+                };
+
+                *position += 1;
+
+                if line.starts_with('+') || line.starts_with(' ') || line.is_empty() {
+                    lines.insert(new_line, *position);
+                    new_line += 1;
+                }
+            }
+
+            files.insert(file.path.to_string(), lines);
+        }
+
+        Self(files)
+    }
+
+    /// Finds the position of the last commentable line within the given range.
+    pub fn find(&self, path: &str, start_line: u64, end_line: u64) -> Option<u64> {
+        let lines = self.0.get(path)?;
+
+        (start_line.min(end_line)..=end_line.max(start_line))
+            .rev()
+            .find_map(|line| lines.get(&line).copied())
+    }
+}
+
+/// Replaces Anno's summary section in a description, or adds it after the author's text.
+fn replace_summary_section(body: &str, summary: &str, sha: &str) -> String {
+    let existing_section = find_summary_section(body);
+    let text_before = match existing_section {
+        Some((start, _)) => &body[..start],
+        None => body,
+    };
+
+    let mut section = format!("{SUMMARY_START_MARKER} sha={sha} -->\n");
+
+    if !summary.is_empty() {
+        // Separate the summary from the author's text, inside the section so it's replaced
+        // along with it
+        if !text_before.trim().is_empty() {
+            section.push_str("<hr>\n\n");
+        }
+
+        section.push_str(&format!("{summary}\n"));
+    }
+
+    section.push_str(SUMMARY_END_MARKER);
+
+    match existing_section {
+        Some((start, end)) => format!("{}{section}{}", &body[..start], &body[end..]),
+        None if body.trim().is_empty() => section,
+        None => format!("{}\n\n{section}", body.trim_end()),
+    }
+}
+
+/// Removes Anno's summary section from a description, leaving only the author's text.
+pub fn remove_summary_section(body: &str) -> String {
+    match find_summary_section(body) {
+        Some((start, end)) => format!("{}{}", &body[..start], &body[end..]),
+        None => body.to_string(),
+    }
+}
+
+/// Finds the start and end of Anno's summary section in a PR description.
+pub(super) fn find_summary_section(body: &str) -> Option<(usize, usize)> {
+    let start = body.find(SUMMARY_START_MARKER)?;
+    let end = body[start..].find(SUMMARY_END_MARKER)? + start;
+
+    Some((start, end + SUMMARY_END_MARKER.len()))
 }
 
+/// A comment on a line of the diff, rather than in the PR's conversation.
 #[derive(Deserialize)]
-pub struct Comment {
-    body: String,
+pub struct InlineComment {
+    pub id: u64,
+    pub body: String,
     url: String,
     node_id: String,
-    created_at: String,
+    pub path: String,
+    /// The hunk the comment was left on.
+    pub diff_hunk: String,
+    /// The first comment of the thread, for replies.
+    pub in_reply_to_id: Option<u64>,
+    pub user: User,
+    performed_via_github_app: Option<GithubApp>,
 }
 
-impl Comment {
-    pub fn is_positive(&self) -> bool {
-        self.is_by_anno() && self.body.contains("LGTM")
+impl InlineComment {
+    pub fn is_finding(&self) -> bool {
+        self.user.is_anno(self.performed_via_github_app.as_ref())
+            && CommentMetadata::parse(&self.body).is_some_and(|m| m.kind == CommentKind::Finding)
     }
 
-    pub fn is_by_anno(&self) -> bool {
-        self.body.starts_with("<!-- anno -->")
-    }
-
-    pub async fn hide_as_outdated(&self) -> Result<()> {
-        tracing::info!("Marking comment {} as outdated", &self.node_id);
-
-        let gh_token = AccessToken::get().await?;
-        let mutation = format!(
-            r#"
-            mutation {{
-                minimizeComment(input: {{
-                    subjectId: "{comment_id}",
-                    classifier: OUTDATED
-                }}) {{
-                    minimizedComment {{
-                        isMinimized
-                    }}
-                }}
-            }}"#,
-            comment_id = &self.node_id
+    /// Gets the body without Anno's hidden metadata.
+    pub fn get_content(&self) -> &str {
+        CommentMetadata::strip(&self.body)
+    }
+
+    /// Hides a finding as outdated, and marks it so it isn't cleared or answered again.
+    pub async fn mark_outdated(&self, client: &Client) -> Result<()> {
+        hide_as_outdated(client, &self.node_id).await?;
+
+        let gh_token = client.token();
+        let body = format!(
+            "{}{}",
+            CommentMetadata::new(CommentKind::OutdatedFinding).to_marker(),
+            self.get_content()
         );
 
         reqwest::Client::new()
-            .post("https://api.github.com/graphql")
+            .patch(&self.url)
             .bearer_auth(gh_token)
             .header("Accept", "application/json")
             .header("User-Agent", "Anno")
-            .json(&json!({ "query": mutation }))
+            .json(&json!({ "body": body }))
             .send()
             .await?
             .error_for_status()
-            .inspect_err(|e| tracing::error!("Error hiding GitHub comment: {e}"))?;
+            .inspect_err(|e| tracing::error!("Error updating inline comment: {e}"))?;
 
         Ok(())
     }
+}
+
+#[derive(Deserialize)]
+pub struct Comment {
+    body: String,
+    url: String,
+    node_id: String,
+    created_at: String,
+    user: User,
+    performed_via_github_app: Option<GithubApp>,
+    /// Parsed from the body when the comments are listed, if Anno posted the comment.
+    #[serde(skip)]
+    metadata: Option<CommentMetadata>,
+}
+
+impl Comment {
+    pub fn is_by_anno(&self) -> bool {
+        self.metadata.is_some()
+    }
+
+    pub fn is_review(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .is_some_and(|m| m.kind == CommentKind::Review)
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .is_some_and(|m| m.kind == CommentKind::Review && m.verdict == Some(Verdict::Positive))
+    }
+
+    pub fn is_ignore_marker(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .is_some_and(|m| m.kind == CommentKind::Ignore)
+    }
+
+    pub fn get_metadata(&self) -> Option<&CommentMetadata> {
+        self.metadata.as_ref()
+    }
 
-    pub async fn delete(&self) -> Result<()> {
+    /// Gets the body without Anno's hidden metadata.
+    pub fn get_content(&self) -> &str {
+        CommentMetadata::strip(&self.body)
+    }
+
+    pub async fn hide_as_outdated(&self, client: &Client) -> Result<()> {
+        hide_as_outdated(client, &self.node_id).await
+    }
+
+    pub async fn delete(&self, client: &Client) -> Result<()> {
         tracing::info!("Deleting comment {}", &self.node_id);
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
 
         reqwest::Client::new()
             .delete(&self.url)
@@ -262,6 +740,39 @@ impl Comment {
     }
 }
 
+/// Minimizes a comment or inline comment as outdated, given its GraphQL node ID.
+async fn hide_as_outdated(client: &Client, node_id: &str) -> Result<()> {
+    tracing::info!("Marking comment {node_id} as outdated");
+
+    let gh_token = client.token();
+    let mutation = format!(
+        r#"
+        mutation {{
+            minimizeComment(input: {{
+                subjectId: "{node_id}",
+                classifier: OUTDATED
+            }}) {{
+                minimizedComment {{
+                    isMinimized
+                }}
+            }}
+        }}"#
+    );
+
+    reqwest::Client::new()
+        .post("https://api.github.com/graphql")
+        .bearer_auth(gh_token)
+        .header("Accept", "application/json")
+        .header("User-Agent", "Anno")
+        .json(&json!({ "query": mutation }))
+        .send()
+        .await?
+        .error_for_status()
+        .inspect_err(|e| tracing::error!("Error hiding GitHub comment: {e}"))?;
+
+    Ok(())
+}
+
 #[derive(Deserialize)]
 pub struct User {
     r#type: UserType,
@@ -271,6 +782,27 @@ impl User {
     pub fn is_bot(&self) -> bool {
         matches!(self.r#type, UserType::Bot)
     }
+
+    /// Whether the user is the bot account of the GitHub App set by `GITHUB_APP_ID`, given the
+    /// app a comment was posted through, so Anno's markers copied into other comments aren't
+    /// trusted. Any bot is trusted when Anno runs with a token rather than as an app.
+    pub fn is_anno(&self, app: Option<&GithubApp>) -> bool {
+        if !self.is_bot() {
+            return false;
+        }
+
+        let Some(app_id) = config::get_optional("GITHUB_APP_ID") else {
+            return true;
+        };
+
+        app.is_some_and(|app| app.id.to_string() == app_id)
+    }
+}
+
+/// The GitHub App a comment was posted through.
+#[derive(Deserialize)]
+pub struct GithubApp {
+    id: u64,
 }
 
 #[derive(Deserialize)]
@@ -0,0 +1,620 @@
+use crate::utils::{config, diff};
+use glob::Pattern;
+use regex_lite::Regex;
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt};
+
+pub const REPO_CONFIG_PATH: &str = ".anno.yml";
+pub const REVIEW_GUIDELINES_PATH: &str = ".anno/review.md";
+pub const SUMMARY_GUIDELINES_PATH: &str = ".anno/summary.md";
+
+const SIZE_LABEL_PREFIX: &str = "size: ";
+const TYPE_LABEL_PREFIX: &str = "type: ";
+/// Largest number of changed lines for each size label, with anything bigger labelled `XL`.
+const SIZE_LABELS: [(usize, &str); 4] = [(10, "XS"), (50, "S"), (250, "M"), (1000, "L")];
+
+/// Per-repo settings read from `.anno.yml` on the default branch. Every section is optional,
+/// and a repo without the file behaves the same as one with an empty file.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RepoConfig {
+    /// Globs of the files Anno looks at, where a `!` prefix excludes matching files.
+    pub paths: Vec<String>,
+    /// Globs of the files Anno ignores, on top of any `!` entries in `paths`.
+    pub paths_ignore: Vec<String>,
+    pub summary: SummaryConfig,
+    pub review: ReviewConfig,
+    pub triggers: TriggersConfig,
+    pub labels: LabelsConfig,
+    pub jira: JiraConfig,
+    pub notifications: NotificationsConfig,
+    #[serde(skip)]
+    included: Vec<Pattern>,
+    #[serde(skip)]
+    excluded: Vec<Pattern>,
+}
+
+impl RepoConfig {
+    pub fn from_yaml(content: &str) -> Result<Self, InvalidRepoConfig> {
+        let mut repo_config: Self =
+            serde_yaml::from_str(content).map_err(|err| InvalidRepoConfig::new(err.to_string()))?;
+
+        repo_config.validate()?;
+
+        Ok(repo_config)
+    }
+
+    pub fn has_paths(&self) -> bool {
+        !self.paths.is_empty() || !self.paths_ignore.is_empty()
+    }
+
+    pub fn is_path_included(&self, path: &str) -> bool {
+        let is_included = self.included.is_empty() || self.included.iter().any(|p| p.matches(path));
+        let is_excluded = self.excluded.iter().any(|p| p.matches(path));
+
+        is_included && !is_excluded
+    }
+
+    /// Removes the files outside of the configured paths from a diff.
+    pub fn filter_diff(&self, pr_diff: &str) -> String {
+        if !self.has_paths() {
+            return pr_diff.to_string();
+        }
+
+        diff::split_by_file(pr_diff)
+            .into_iter()
+            .filter(|file| self.is_path_included(file.path))
+            .map(|file| file.content)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn validate(&mut self) -> Result<(), InvalidRepoConfig> {
+        for (field, paths) in [("paths", &self.paths), ("paths_ignore", &self.paths_ignore)] {
+            for (i, path) in paths.iter().enumerate() {
+                let is_excluded = field == "paths_ignore" || path.starts_with('!');
+                let pattern =
+                    Pattern::new(path.strip_prefix('!').unwrap_or(path)).map_err(|err| {
+                        InvalidRepoConfig::new(format!("`{field}[{i}]` isn't a valid glob: {err}"))
+                    })?;
+
+                if is_excluded {
+                    self.excluded.push(pattern);
+                } else {
+                    self.included.push(pattern);
+                }
+            }
+        }
+
+        for (i, path) in self.review.security.paths.iter().enumerate() {
+            Pattern::new(path).map_err(|err| {
+                InvalidRepoConfig::new(format!(
+                    "`review.security.paths[{i}]` isn't a valid glob: {err}"
+                ))
+            })?;
+        }
+
+        for (field, labels) in [("areas", &self.labels.areas), ("flags", &self.labels.flags)] {
+            for (label, paths) in labels {
+                if label.trim().is_empty() {
+                    return Err(InvalidRepoConfig::new(format!(
+                        "`labels.{field}` shouldn't have an empty label"
+                    )));
+                }
+
+                for (i, path) in paths.iter().enumerate() {
+                    Pattern::new(path).map_err(|err| {
+                        InvalidRepoConfig::new(format!(
+                            "`labels.{field}.{label}[{i}]` isn't a valid glob: {err}"
+                        ))
+                    })?;
+                }
+            }
+        }
+
+        if let Some(i) = self
+            .summary
+            .categories
+            .iter()
+            .position(|c| c.trim().is_empty())
+        {
+            return Err(InvalidRepoConfig::new(format!(
+                "`summary.categories[{i}]` shouldn't be empty"
+            )));
+        }
+
+        let key_regex = Regex::new(r"^[A-Z][A-Z0-9]{1,9}$").expect("Valid regex");
+
+        for (i, key) in self.jira.project_keys.iter().enumerate() {
+            if !key_regex.is_match(key) {
+                return Err(InvalidRepoConfig::new(format!(
+                    "`jira.project_keys[{i}]` should be a Jira project key like `ABC`, got '{key}'"
+                )));
+            }
+        }
+
+        if self
+            .notifications
+            .slack
+            .webhook_url_env
+            .as_ref()
+            .is_some_and(|env| env.trim().is_empty())
+        {
+            return Err(InvalidRepoConfig::new(
+                "`notifications.slack.webhook_url_env` shouldn't be empty".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SummaryConfig {
+    /// Headings to group release summaries under, instead of the default categories.
+    pub categories: Vec<String>,
+    /// Number of lines changed since a PR was summarised that causes pushes to refresh the
+    /// summary. PRs are only summarised when opened if unset.
+    pub refresh_threshold: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReviewConfig {
+    pub strictness: Strictness,
+    /// Least severe finding reviews mention, overriding `strictness`.
+    min_severity: Option<Severity>,
+    /// Least severe finding that makes a review negative.
+    pub blocking_severity: Severity,
+    pub security: SecurityReviewConfig,
+    /// Where reviews are published, overriding `REVIEW_OUTPUT`.
+    output: Option<ReviewOutput>,
+}
+
+impl Default for ReviewConfig {
+    fn default() -> Self {
+        Self {
+            strictness: Strictness::default(),
+            min_severity: None,
+            blocking_severity: Severity::Minor,
+            security: SecurityReviewConfig::default(),
+            output: None,
+        }
+    }
+}
+
+impl ReviewConfig {
+    pub fn get_min_severity(&self) -> Severity {
+        if let Some(min_severity) = self.min_severity {
+            return min_severity;
+        }
+
+        match self.strictness {
+            Strictness::Lenient => Severity::Major,
+            Strictness::Standard => Severity::Minor,
+            Strictness::Strict => Severity::Info,
+        }
+    }
+
+    pub fn output(&self) -> ReviewOutput {
+        if let Some(output) = self.output {
+            return output;
+        }
+
+        match config::get_optional("REVIEW_OUTPUT").as_deref() {
+            None | Some("comment") => ReviewOutput::Comment,
+            Some("check_run") => ReviewOutput::CheckRun,
+            Some(output) => {
+                panic!("REVIEW_OUTPUT should be one of 'comment' or 'check_run', got '{output}'")
+            }
+        }
+    }
+}
+
+/// A second review pass looking for security issues, which the general review is too cautious to
+/// raise.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityReviewConfig {
+    /// Reviews every PR for security issues, rather than only those changing `paths`.
+    pub enabled: bool,
+    /// Globs of sensitive files that trigger a security review when changed.
+    pub paths: Vec<String>,
+}
+
+impl Default for SecurityReviewConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paths: [
+                "**/*auth*",
+                "**/*login*",
+                "**/*session*",
+                "**/*password*",
+                "**/*token*",
+                "**/*secret*",
+                "**/*crypto*",
+                "**/*payment*",
+                "**/*billing*",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+impl SecurityReviewConfig {
+    /// Whether a diff should get a security review, because it's turned on for the repo or
+    /// the diff changes a sensitive file.
+    pub fn should_review(&self, pr_diff: &str) -> bool {
+        if self.enabled {
+            return true;
+        }
+
+        // The globs are checked when the config is read, so invalid ones can't get this far
+        let patterns = self
+            .paths
+            .iter()
+            .filter_map(|path| Pattern::new(path).ok())
+            .collect::<Vec<_>>();
+
+        diff::split_by_file(pr_diff)
+            .iter()
+            .any(|file| patterns.iter().any(|p| p.matches(file.path)))
+    }
+}
+
+/// How minor an issue has to be before reviews stop mentioning it.
+#[derive(Deserialize, Default, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Strictness {
+    /// Only blocker and major findings.
+    Lenient,
+    /// Blocker, major and minor findings.
+    Standard,
+    /// Every finding.
+    #[default]
+    Strict,
+}
+
+/// How severe a review finding is, ordered from least to most severe.
+#[derive(Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Minor,
+    Major,
+    Blocker,
+}
+
+impl Severity {
+    /// Ordered from most to least severe.
+    pub const ALL: [Self; 4] = [Self::Blocker, Self::Major, Self::Minor, Self::Info];
+
+    /// The name used in `.anno.yml` and by the LLM.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Minor => "minor",
+            Self::Major => "major",
+            Self::Blocker => "blocker",
+        }
+    }
+
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            Self::Info => "Info",
+            Self::Minor => "Minor",
+            Self::Major => "Major",
+            Self::Blocker => "Blocker",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewOutput {
+    Comment,
+    CheckRun,
+}
+
+/// Which pull request actions, beyond being opened and pushed to, Anno responds to.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TriggersConfig {
+    /// Summarises and reviews draft PRs, rather than waiting until they're ready for review.
+    pub drafts: bool,
+    /// Summarises and reviews PRs again when they're marked as ready for review.
+    pub ready_for_review: bool,
+    /// Summarises and reviews PRs again when they're reopened.
+    pub reopened: bool,
+    /// Updates the Jira issue links when a PR's title or description mentions new issues.
+    pub edited: bool,
+}
+
+impl Default for TriggersConfig {
+    fn default() -> Self {
+        Self {
+            drafts: false,
+            ready_for_review: true,
+            reopened: true,
+            edited: true,
+        }
+    }
+}
+
+impl TriggersConfig {
+    pub fn is_enabled(&self, action: &str) -> bool {
+        match action {
+            "ready_for_review" => self.ready_for_review,
+            "reopened" => self.reopened,
+            "edited" => self.edited,
+            _ => true,
+        }
+    }
+}
+
+/// Labels Anno adds to PRs when they're opened, so they can be filtered without relying on
+/// authors to set them.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LabelsConfig {
+    pub enabled: bool,
+    /// Labels for areas of the codebase, each added when a PR changes a file matching one of its
+    /// globs.
+    pub areas: BTreeMap<String, Vec<String>>,
+    /// Labels for changes worth a closer look, added the same way as `areas`.
+    pub flags: BTreeMap<String, Vec<String>>,
+    /// Adds a `size: ` label from the number of lines changed.
+    pub size: bool,
+    /// Adds a `type: ` label for the kind of change, as judged when summarising the PR.
+    #[serde(rename = "type")]
+    pub change_type: bool,
+}
+
+impl Default for LabelsConfig {
+    fn default() -> Self {
+        let flags = [
+            (
+                "has-migration",
+                vec!["**/migrations/**", "**/migrate/**", "**/*migration*"],
+            ),
+            (
+                "touches-ci",
+                vec![
+                    ".github/workflows/**",
+                    ".github/actions/**",
+                    ".gitlab-ci.yml",
+                    ".circleci/**",
+                    "**/Jenkinsfile",
+                ],
+            ),
+        ];
+
+        Self {
+            enabled: false,
+            areas: BTreeMap::new(),
+            flags: flags
+                .into_iter()
+                .map(|(label, paths)| {
+                    (
+                        label.to_string(),
+                        paths.into_iter().map(String::from).collect(),
+                    )
+                })
+                .collect(),
+            size: true,
+            change_type: true,
+        }
+    }
+}
+
+impl LabelsConfig {
+    /// Works out the labels to add to a PR: the areas and flags matching the changed files, the
+    /// size of the diff and the type of change, when it's known. Size and type labels replace
+    /// any the PR already has, as they change between pushes, and other labels are left alone.
+    pub fn get_label_changes(
+        &self,
+        existing: &[String],
+        pr_diff: &str,
+        change_type: Option<ChangeType>,
+    ) -> LabelChanges {
+        let files = diff::split_by_file(pr_diff);
+        let mut new_labels = Vec::new();
+
+        // The globs are checked when the config is read, so invalid ones can't get this far
+        for (label, paths) in self.areas.iter().chain(&self.flags) {
+            let patterns = paths
+                .iter()
+                .filter_map(|path| Pattern::new(path).ok())
+                .collect::<Vec<_>>();
+
+            if files
+                .iter()
+                .any(|file| patterns.iter().any(|p| p.matches(file.path)))
+            {
+                new_labels.push(label.clone());
+            }
+        }
+
+        if self.size {
+            let changed_lines = diff::count_changed_lines(pr_diff);
+            let size = SIZE_LABELS
+                .iter()
+                .find(|(max_lines, _)| changed_lines <= *max_lines)
+                .map_or("XL", |(_, size)| size);
+
+            new_labels.push(format!("{SIZE_LABEL_PREFIX}{size}"));
+        }
+
+        if self.change_type
+            && let Some(change_type) = change_type.filter(|t| *t != ChangeType::Other)
+        {
+            new_labels.push(format!("{TYPE_LABEL_PREFIX}{}", change_type.as_str()));
+        }
+
+        let replaced_prefixes = [SIZE_LABEL_PREFIX, TYPE_LABEL_PREFIX]
+            .into_iter()
+            .filter(|prefix| new_labels.iter().any(|l| l.starts_with(prefix)))
+            .collect::<Vec<_>>();
+
+        let removed = existing
+            .iter()
+            .filter(|label| {
+                replaced_prefixes.iter().any(|p| label.starts_with(p))
+                    && !new_labels.contains(label)
+            })
+            .cloned()
+            .collect();
+
+        new_labels.retain(|label| !existing.contains(label));
+
+        LabelChanges {
+            added: new_labels,
+            removed,
+        }
+    }
+}
+
+/// Labels to add to and remove from a PR, so labels added by people in the meantime are kept.
+#[derive(Debug, Default, PartialEq)]
+pub struct LabelChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl LabelChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The kind of change a PR makes.
+#[derive(Deserialize, Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeType {
+    Feature,
+    Bugfix,
+    Refactor,
+    Deps,
+    #[default]
+    Other,
+}
+
+impl ChangeType {
+    pub const ALL: [Self; 5] = [
+        Self::Feature,
+        Self::Bugfix,
+        Self::Refactor,
+        Self::Deps,
+        Self::Other,
+    ];
+
+    /// The name used in labels and by the LLM.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Feature => "feature",
+            Self::Bugfix => "bugfix",
+            Self::Refactor => "refactor",
+            Self::Deps => "deps",
+            Self::Other => "other",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct JiraConfig {
+    pub enabled: bool,
+    /// Projects to link issues from. Issues from any project are linked when empty.
+    pub project_keys: Vec<String>,
+}
+
+impl Default for JiraConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            project_keys: Vec::new(),
+        }
+    }
+}
+
+impl JiraConfig {
+    /// Whether Jira is set up for Anno and hasn't been turned off for the repo.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled && config::get_optional("JIRA_API_KEY").is_some()
+    }
+
+    pub fn is_issue_included(&self, key: &str) -> bool {
+        let project_key = key.split('-').next().unwrap_or_default();
+
+        self.project_keys.is_empty() || self.project_keys.iter().any(|k| k == project_key)
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    pub slack: SlackConfig,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlackConfig {
+    pub enabled: bool,
+    /// Environment variable holding the webhook URL to post to, so repos can route release
+    /// summaries to different channels without committing the URL.
+    pub webhook_url_env: Option<String>,
+}
+
+impl Default for SlackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            webhook_url_env: None,
+        }
+    }
+}
+
+impl SlackConfig {
+    pub fn get_webhook_url(&self) -> String {
+        config::get(
+            self.webhook_url_env
+                .as_deref()
+                .unwrap_or("SLACK_WEBHOOK_URL"),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidRepoConfig {
+    message: String,
+    /// Blob SHA of the invalid file, once it's known which version of it was read.
+    sha: Option<String>,
+}
+
+impl InvalidRepoConfig {
+    fn new(message: String) -> Self {
+        Self { message, sha: None }
+    }
+
+    pub(crate) fn with_sha(self, sha: &str) -> Self {
+        Self {
+            sha: Some(sha.to_string()),
+            ..self
+        }
+    }
+
+    pub fn sha(&self) -> Option<&str> {
+        self.sha.as_deref()
+    }
+}
+
+impl fmt::Display for InvalidRepoConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid {REPO_CONFIG_PATH}: {}", self.message)
+    }
+}
+
+impl std::error::Error for InvalidRepoConfig {}
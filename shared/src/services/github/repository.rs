@@ -1,12 +1,32 @@
-use super::{pull_request::PullRequest, AccessToken};
+use super::{
+    check_run::{CheckRun, CreatedCheckRun},
+    pr_template::{PrTemplate, PR_TEMPLATE_PATHS},
+    pull_request::{InlineComment, PullRequest},
+    repo_config::{RepoConfig, REPO_CONFIG_PATH},
+    Client,
+};
+use crate::utils::{code_index::SourceFile, config, diff, redact};
 use anyhow::Result;
+use base64::prelude::*;
+use flate2::read::GzDecoder;
 use serde::Deserialize;
+use serde_json::json;
+use std::io::Read;
+
+/// GitHub only accepts this many annotations per request, so the rest are sent as updates.
+const MAX_ANNOTATIONS_PER_REQUEST: usize = 50;
+
+const MAX_GUIDELINES_TOKENS: usize = 4_000;
+
+/// Tarballs larger than this aren't downloaded, to keep memory use down on very large repos.
+const MAX_TARBALL_SIZE: u64 = 100_000_000;
 
 #[derive(Deserialize)]
 pub struct Repository {
     pub full_name: String,
     pub name: String,
     pub html_url: String,
+    url: String,
     pulls_url: String,
     compare_url: String,
     contents_url: String,
@@ -26,10 +46,14 @@ impl Repository {
         format!("{}/commit/{sha}", self.html_url)
     }
 
-    pub async fn get_pull_requests_for_commit(&self, sha: &str) -> Result<Vec<PullRequest>> {
+    pub async fn get_pull_requests_for_commit(
+        &self,
+        client: &Client,
+        sha: &str,
+    ) -> Result<Vec<PullRequest>> {
         tracing::info!("Fetching associated pull requests for commit {sha}");
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
         let url = self.commits_url.replace("{/sha}", &format!("/{sha}/pulls"));
 
         let response = reqwest::Client::new()
@@ -47,10 +71,10 @@ impl Repository {
         Ok(response)
     }
 
-    pub async fn get_pull_request(&self, id: &str) -> Result<Option<PullRequest>> {
+    pub async fn get_pull_request(&self, client: &Client, id: &str) -> Result<Option<PullRequest>> {
         tracing::info!("Fetching pull request #{id}");
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
         let url = self.pulls_url.replace("{/number}", &format!("/{id}"));
 
         let response = match reqwest::Client::new()
@@ -79,10 +103,71 @@ impl Repository {
         Ok(Some(pull_request))
     }
 
-    pub async fn get_file(&self, path: &str) -> Result<RepoFile> {
+    pub async fn get_inline_comment(
+        &self,
+        client: &Client,
+        id: u64,
+    ) -> Result<Option<InlineComment>> {
+        tracing::info!("Fetching inline comment {id}");
+
+        let gh_token = client.token();
+
+        let response = match reqwest::Client::new()
+            .get(format!("{}/pulls/comments/{id}", self.url))
+            .bearer_auth(gh_token)
+            .header("Accept", "application/json")
+            .header("User-Agent", "Anno")
+            .send()
+            .await?
+            .error_for_status()
+        {
+            Ok(res) => res,
+            Err(err) => {
+                tracing::error!("Error getting inline comment: {err}");
+
+                if err.status() == Some(reqwest::StatusCode::NOT_FOUND) {
+                    return Ok(None);
+                }
+
+                Err(err)
+            }?,
+        };
+
+        let comment: InlineComment = response.json().await?;
+
+        Ok(Some(comment))
+    }
+
+    pub async fn update_inline_comment(&self, client: &Client, id: u64, body: &str) -> Result<()> {
+        tracing::info!("Updating inline comment {id}");
+
+        let pr_comment_enabled = config::get("PR_COMMENT_ENABLED") == "true";
+
+        if !pr_comment_enabled {
+            println!("{body}");
+            return Ok(());
+        }
+
+        let gh_token = client.token();
+
+        reqwest::Client::new()
+            .patch(format!("{}/pulls/comments/{id}", self.url))
+            .bearer_auth(gh_token)
+            .header("Accept", "application/json")
+            .header("User-Agent", "Anno")
+            .json(&json!({ "body": body }))
+            .send()
+            .await?
+            .error_for_status()
+            .inspect_err(|e| tracing::error!("Error updating inline comment: {e}"))?;
+
+        Ok(())
+    }
+
+    pub async fn get_file(&self, client: &Client, path: &str) -> Result<RepoFile> {
         tracing::info!("Fetching file {path}");
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
         let url = self.contents_url.replace("{+path}", path);
 
         let response = reqwest::Client::new()
@@ -100,10 +185,131 @@ impl Repository {
         Ok(response)
     }
 
-    pub async fn get_diff_for_commit(&self, sha: &str) -> Result<String> {
+    /// Gets a file from the default branch, or `None` if the repo doesn't have it.
+    pub async fn find_file(&self, client: &Client, path: &str) -> Result<Option<RepoFile>> {
+        self.find_file_at(client, path, &self.default_branch).await
+    }
+
+    /// Gets a file as it is at a branch or commit, or `None` if it doesn't exist there.
+    pub async fn find_file_at(
+        &self,
+        client: &Client,
+        path: &str,
+        git_ref: &str,
+    ) -> Result<Option<RepoFile>> {
+        tracing::info!("Fetching file {path} at {git_ref} if it exists");
+
+        let gh_token = client.token();
+        let url = self.contents_url.replace("{+path}", path);
+
+        let response = match reqwest::Client::new()
+            .get(url)
+            .query(&[("ref", git_ref)])
+            .bearer_auth(gh_token)
+            .header("Accept", "application/json")
+            .header("User-Agent", "Anno")
+            .send()
+            .await?
+            .error_for_status()
+        {
+            Ok(res) => res,
+            Err(err) => {
+                if err.status() == Some(reqwest::StatusCode::NOT_FOUND) {
+                    return Ok(None);
+                }
+
+                tracing::error!("Error getting repo file: {err}");
+                Err(err)
+            }?,
+        };
+
+        let file = response.json::<RepoFile>().await?;
+
+        Ok(Some(file))
+    }
+
+    /// Gets the `.anno.yml` config from the default branch, falling back to the defaults when
+    /// the repo doesn't have one. Errors with `InvalidRepoConfig` if the file is invalid.
+    pub async fn get_repo_config(&self, client: &Client) -> Result<RepoConfig> {
+        let Some(file) = self.find_file(client, REPO_CONFIG_PATH).await? else {
+            return Ok(RepoConfig::default());
+        };
+
+        Ok(RepoConfig::from_yaml(&file.decode()?).map_err(|err| err.with_sha(&file.sha))?)
+    }
+
+    /// Gets a markdown guidelines file like `.anno/review.md` to add to prompts, cut down to a
+    /// size that leaves most of the prompt for the diff.
+    pub async fn get_guidelines(&self, client: &Client, path: &str) -> Result<Option<String>> {
+        let Some(file) = self.find_file(client, path).await? else {
+            return Ok(None);
+        };
+
+        let guidelines = file.decode()?;
+
+        if diff::estimate_tokens(&guidelines) > MAX_GUIDELINES_TOKENS {
+            tracing::warn!("{path} exceeds {MAX_GUIDELINES_TOKENS} tokens, truncating");
+        }
+
+        let guidelines = diff::truncate_to_tokens(&guidelines, MAX_GUIDELINES_TOKENS).trim();
+
+        Ok((!guidelines.is_empty()).then(|| guidelines.to_string()))
+    }
+
+    /// Gets the repo's pull request template from the first location GitHub would use.
+    pub async fn get_pr_template(&self, client: &Client) -> Result<Option<PrTemplate>> {
+        for path in PR_TEMPLATE_PATHS {
+            if let Some(file) = self.find_file(client, path).await? {
+                return Ok(Some(PrTemplate::parse(&file.decode()?)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Downloads the repo at a commit as a tarball and reads the files `include` accepts, given
+    /// their path and size in bytes. It's one request however many files are read, where the
+    /// contents API would need one for each. Files that aren't UTF-8 are skipped.
+    pub async fn get_files_at(
+        &self,
+        client: &Client,
+        sha: &str,
+        include: impl Fn(&str, u64) -> bool + Send + 'static,
+    ) -> Result<Vec<SourceFile>> {
+        tracing::info!("Fetching tarball for commit {sha}");
+
+        let gh_token = client.token();
+
+        let response = reqwest::Client::new()
+            .get(format!("{}/tarball/{sha}", self.url))
+            .bearer_auth(gh_token)
+            .header("User-Agent", "Anno")
+            .send()
+            .await?
+            .error_for_status()
+            .inspect_err(|e| tracing::error!("Error getting tarball: {e}"))?;
+
+        if response
+            .content_length()
+            .is_some_and(|size| size > MAX_TARBALL_SIZE)
+        {
+            anyhow::bail!("Tarball for commit {sha} is over {MAX_TARBALL_SIZE} bytes");
+        }
+
+        let tarball = response.bytes().await?;
+
+        if tarball.len() as u64 > MAX_TARBALL_SIZE {
+            anyhow::bail!("Tarball for commit {sha} is over {MAX_TARBALL_SIZE} bytes");
+        }
+
+        // Unpacking is CPU bound, so it's kept off the async runtime's threads
+        tokio::task::spawn_blocking(move || read_tarball(&tarball, include)).await?
+    }
+
+    pub async fn get_diff_for_commit(&self, client: &Client, sha: &str) -> Result<String> {
         tracing::info!("Fetching diff for commit {sha}");
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
         let url = self.commits_url.replace("{/sha}", &format!("/{sha}"));
 
         let diff = reqwest::Client::new()
@@ -118,13 +324,13 @@ impl Repository {
             .text()
             .await?;
 
-        Ok(diff)
+        Ok(redact::redact_diff(&diff))
     }
 
-    pub async fn get_commit_message(&self, sha: &str) -> Result<String> {
+    pub async fn get_commit_message(&self, client: &Client, sha: &str) -> Result<String> {
         tracing::info!("Fetching commit message for commit {sha}");
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
         let url = self.commits_url.replace("{/sha}", &format!("/{sha}"));
 
         let message = reqwest::Client::new()
@@ -144,10 +350,15 @@ impl Repository {
         Ok(message)
     }
 
-    pub async fn get_diff_between_commits(&self, old_sha: &str, new_sha: &str) -> Result<String> {
+    pub async fn get_diff_between_commits(
+        &self,
+        client: &Client,
+        old_sha: &str,
+        new_sha: &str,
+    ) -> Result<String> {
         tracing::info!("Fetching diff between commits {old_sha} and {new_sha}");
 
-        let gh_token = AccessToken::get().await?;
+        let gh_token = client.token();
         let url = self
             .compare_url
             .replace("{base}...{head}", &format!("{old_sha}...{new_sha}"));
@@ -164,7 +375,97 @@ impl Repository {
             .text()
             .await?;
 
-        Ok(diff)
+        Ok(redact::redact_diff(&diff))
+    }
+
+    /// Whether `new_sha` only adds commits on top of `old_sha` without merging another branch in,
+    /// so the diff between them is just the changes those commits make.
+    pub async fn is_linear_history(
+        &self,
+        client: &Client,
+        old_sha: &str,
+        new_sha: &str,
+    ) -> Result<bool> {
+        tracing::info!("Comparing commits {old_sha} and {new_sha}");
+
+        let gh_token = client.token();
+        let url = self
+            .compare_url
+            .replace("{base}...{head}", &format!("{old_sha}...{new_sha}"));
+
+        let comparison = reqwest::Client::new()
+            .get(url)
+            .bearer_auth(gh_token)
+            .header("Accept", "application/json")
+            .header("User-Agent", "Anno")
+            .send()
+            .await?
+            .error_for_status()
+            .inspect_err(|e| tracing::error!("Error comparing commits: {e}"))?
+            .json::<Comparison>()
+            .await?;
+
+        Ok(comparison.status == "ahead" && comparison.commits.iter().all(|c| c.parents.len() == 1))
+    }
+
+    /// Creates a completed Check Run, which needs a GitHub App token with `checks:write`.
+    pub async fn create_check_run(&self, client: &Client, check_run: &CheckRun) -> Result<()> {
+        tracing::info!(
+            "Creating '{}' check run for {} with conclusion {:?}",
+            check_run.name,
+            check_run.head_sha,
+            check_run.conclusion
+        );
+
+        let pr_comment_enabled = config::get("PR_COMMENT_ENABLED") == "true";
+
+        if !pr_comment_enabled {
+            println!("{}", check_run.summary);
+            for annotation in &check_run.annotations {
+                println!(
+                    "{}:{}-{} {}",
+                    annotation.path, annotation.start_line, annotation.end_line, annotation.message
+                );
+            }
+            return Ok(());
+        }
+
+        let gh_token = client.token();
+        let mut annotations = check_run.annotations.chunks(MAX_ANNOTATIONS_PER_REQUEST);
+
+        let created = reqwest::Client::new()
+            .post(format!("{}/check-runs", self.url))
+            .bearer_auth(gh_token)
+            .header("Accept", "application/json")
+            .header("User-Agent", "Anno")
+            .json(&json!({
+                "name": check_run.name,
+                "head_sha": check_run.head_sha,
+                "status": "completed",
+                "conclusion": check_run.conclusion,
+                "output": check_run.get_output(annotations.next().unwrap_or_default()),
+            }))
+            .send()
+            .await?
+            .error_for_status()
+            .inspect_err(|e| tracing::error!("Error creating check run: {e}"))?
+            .json::<CreatedCheckRun>()
+            .await?;
+
+        for batch in annotations {
+            reqwest::Client::new()
+                .patch(&created.url)
+                .bearer_auth(gh_token)
+                .header("Accept", "application/json")
+                .header("User-Agent", "Anno")
+                .json(&json!({ "output": check_run.get_output(batch) }))
+                .send()
+                .await?
+                .error_for_status()
+                .inspect_err(|e| tracing::error!("Error adding check run annotations: {e}"))?;
+        }
+
+        Ok(())
     }
 
     pub fn get_compare_to_master_url(&self, commit: &str) -> String {
@@ -178,6 +479,29 @@ impl Repository {
 #[derive(Deserialize)]
 pub struct RepoFile {
     pub content: String,
+    /// Blob SHA of the file's contents.
+    pub sha: String,
+}
+
+impl RepoFile {
+    pub fn decode(&self) -> Result<String> {
+        let decoded = BASE64_STANDARD.decode(self.content.replace('\n', ""))?;
+
+        Ok(String::from_utf8(decoded)?)
+    }
+}
+
+#[derive(Deserialize)]
+struct Comparison {
+    /// `ahead` when the head only adds commits to the base, or `diverged` when the base isn't an
+    /// ancestor of it.
+    status: String,
+    commits: Vec<ComparedCommit>,
+}
+
+#[derive(Deserialize)]
+struct ComparedCommit {
+    parents: Vec<serde::de::IgnoredAny>,
 }
 
 #[derive(Deserialize)]
@@ -189,3 +513,40 @@ pub struct Commit {
 pub struct CommitDetails {
     pub message: String,
 }
+
+/// Reads the files `include` accepts from a gzipped tarball of a repo.
+fn read_tarball(tarball: &[u8], include: impl Fn(&str, u64) -> bool) -> Result<Vec<SourceFile>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(tarball));
+    let mut files = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        // Paths are inside a top-level `{owner}-{repo}-{sha}` directory
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+        let Some((_, path)) = entry_path.split_once('/') else {
+            continue;
+        };
+
+        if !include(path, entry.size()) {
+            continue;
+        }
+
+        let mut contents = String::new();
+
+        if entry.read_to_string(&mut contents).is_err() {
+            continue;
+        }
+
+        files.push(SourceFile {
+            path: path.to_string(),
+            contents,
+        });
+    }
+
+    Ok(files)
+}
@@ -0,0 +1,65 @@
+use super::{chat_gpt::ChatGpt, claude::Claude, local_llm::LocalLlm};
+use crate::utils::config;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+#[derive(Default)]
+pub struct Request {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub system_prompt: &'static str,
+    pub user_prompt: String,
+    pub schema_name: &'static str,
+    /// JSON schema of the object the response should deserialize into.
+    pub schema: Value,
+}
+
+pub trait Llm {
+    fn send<T: DeserializeOwned>(&self, request: Request) -> impl Future<Output = Result<T>>;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Provider {
+    ChatGpt,
+    Claude,
+    Local,
+}
+
+impl Provider {
+    pub fn from_config_or(default: Self) -> Self {
+        let Some(provider) = config::get_optional("LLM_PROVIDER") else {
+            return default;
+        };
+
+        match provider.to_lowercase().as_str() {
+            "chat_gpt" | "chatgpt" | "openai" => Self::ChatGpt,
+            "claude" | "anthropic" => Self::Claude,
+            "local" => Self::Local,
+            _ => panic!(
+                "LLM_PROVIDER should be one of 'chat_gpt', 'claude' or 'local', got '{provider}'"
+            ),
+        }
+    }
+
+    /// Name of the model requests are sent to, for recording alongside responses.
+    pub fn get_model(&self) -> String {
+        match self {
+            Self::ChatGpt => {
+                config::get_optional("CHAT_GPT_MODEL").unwrap_or_else(|| "gpt-4o".to_string())
+            }
+            Self::Claude => config::get("CLAUDE_MODEL"),
+            Self::Local => config::get("LOCAL_LLM_MODEL"),
+        }
+    }
+}
+
+impl Llm for Provider {
+    async fn send<T: DeserializeOwned>(&self, request: Request) -> Result<T> {
+        match self {
+            Self::ChatGpt => ChatGpt.send(request).await,
+            Self::Claude => Claude.send(request).await,
+            Self::Local => LocalLlm.send(request).await,
+        }
+    }
+}
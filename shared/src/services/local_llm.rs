@@ -0,0 +1,180 @@
+use super::llm::{self, Llm};
+use crate::utils::config;
+use anyhow::{Context, Result};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+
+/// Backend for self-hosted inference servers such as Ollama, llama.cpp server or vLLM.
+///
+/// Most of these servers reject OpenAI's `strict` JSON schema option, so the schema is instead
+/// passed using whichever constrained decoding mechanism the server supports.
+pub struct LocalLlm;
+
+impl Llm for LocalLlm {
+    async fn send<T: DeserializeOwned>(&self, request: llm::Request) -> Result<T> {
+        let base_url = config::get("LOCAL_LLM_BASE_URL");
+        let model = config::get("LOCAL_LLM_MODEL");
+        let api_key = config::get_optional("LOCAL_LLM_API_KEY");
+        let schema_mode = SchemaMode::from_config();
+
+        // Smaller models follow the schema more reliably when it's also part of the prompt
+        let user_prompt = format!(
+            "{}\n<ResponseSchema>{}</ResponseSchema>\nRespond only with JSON that matches the response schema.",
+            request.user_prompt, request.schema
+        );
+
+        let messages = json!([
+            { "role": "system", "content": request.system_prompt },
+            { "role": "user", "content": user_prompt }
+        ]);
+
+        let temperature = request.temperature.unwrap_or(0.0);
+        let max_tokens = request.max_tokens.unwrap_or(2048);
+
+        let (url, body) = match schema_mode {
+            SchemaMode::Ollama => (
+                format!("{base_url}/api/chat"),
+                json!({
+                    "model": model,
+                    "stream": false,
+                    "messages": messages,
+                    "format": request.schema,
+                    "options": { "temperature": temperature, "num_predict": max_tokens }
+                }),
+            ),
+            SchemaMode::JsonSchema => (
+                format!("{base_url}/chat/completions"),
+                json!({
+                    "model": model,
+                    "temperature": temperature,
+                    "max_tokens": max_tokens,
+                    "messages": messages,
+                    "response_format": {
+                        "type": "json_schema",
+                        "json_schema": { "name": request.schema_name, "schema": request.schema }
+                    }
+                }),
+            ),
+            SchemaMode::JsonObject => (
+                format!("{base_url}/chat/completions"),
+                json!({
+                    "model": model,
+                    "temperature": temperature,
+                    "max_tokens": max_tokens,
+                    "messages": messages,
+                    "response_format": { "type": "json_object", "schema": request.schema }
+                }),
+            ),
+            SchemaMode::GuidedJson => (
+                format!("{base_url}/chat/completions"),
+                json!({
+                    "model": model,
+                    "temperature": temperature,
+                    "max_tokens": max_tokens,
+                    "messages": messages,
+                    "guided_json": request.schema
+                }),
+            ),
+        };
+
+        let mut http_request = reqwest::Client::new()
+            .post(url)
+            .header("content-type", "application/json")
+            .json(&body);
+
+        if let Some(api_key) = api_key {
+            http_request = http_request.bearer_auth(api_key);
+        }
+
+        let response = http_request
+            .send()
+            .await?
+            .error_for_status()
+            .inspect_err(|e| tracing::error!("Error making local LLM request: {e}"))?;
+
+        let content = match schema_mode {
+            SchemaMode::Ollama => response.json::<OllamaResponse>().await?.message.content,
+            _ => {
+                response
+                    .json::<CompletionResponse>()
+                    .await?
+                    .choices
+                    .into_iter()
+                    .next()
+                    .context("Local LLM returned no choices")?
+                    .message
+                    .content
+            }
+        };
+
+        let parsed_response: T = serde_json::from_str(strip_code_fence(&content))
+            .inspect_err(|e| tracing::error!("Error parsing local LLM response: {e}"))?;
+
+        Ok(parsed_response)
+    }
+}
+
+/// How the response schema is passed to the server, set with `LOCAL_LLM_SCHEMA_MODE`.
+#[derive(Clone, Copy)]
+enum SchemaMode {
+    /// OpenAI-compatible `response_format.json_schema` without `strict`, e.g. vLLM or LM Studio.
+    JsonSchema,
+    /// `response_format.type = json_object` with a `schema`, as used by llama.cpp server.
+    JsonObject,
+    /// vLLM's `guided_json` extra parameter.
+    GuidedJson,
+    /// Ollama's native `/api/chat` endpoint with a `format` schema.
+    Ollama,
+}
+
+impl SchemaMode {
+    fn from_config() -> Self {
+        let Some(mode) = config::get_optional("LOCAL_LLM_SCHEMA_MODE") else {
+            return Self::JsonSchema;
+        };
+
+        match mode.as_str() {
+            "json_schema" => Self::JsonSchema,
+            "json_object" => Self::JsonObject,
+            "guided_json" => Self::GuidedJson,
+            "ollama" => Self::Ollama,
+            _ => panic!(
+                "LOCAL_LLM_SCHEMA_MODE should be one of 'json_schema', 'json_object', 'guided_json' or 'ollama', got '{mode}'"
+            ),
+        }
+    }
+}
+
+/// Some models wrap their JSON in a markdown code block despite the constrained output.
+fn strip_code_fence(content: &str) -> &str {
+    let content = content.trim();
+
+    let Some(inner) = content.strip_prefix("```") else {
+        return content;
+    };
+
+    inner
+        .trim_start_matches("json")
+        .trim_end_matches("```")
+        .trim()
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+    message: Message,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: String,
+}
@@ -1,4 +1,7 @@
+pub mod aws;
 pub mod chat_gpt;
 pub mod claude;
 pub mod github;
 pub mod jira;
+pub mod llm;
+pub mod local_llm;
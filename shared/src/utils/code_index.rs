@@ -0,0 +1,367 @@
+use super::{diff, redact};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::LazyLock,
+};
+use tree_sitter::{Parser, Query, QueryCursor, StreamingIterator};
+
+/// Files larger than this are left out of the index, as they're usually generated or vendored.
+pub const MAX_FILE_SIZE: usize = 200_000;
+/// Directories that hold dependencies or build output rather than the repo's own code.
+const IGNORED_DIRS: [&str; 5] = ["node_modules", "vendor", "dist", "build", "target"];
+/// Names defined more often than this, like `new` or `get`, are too ambiguous to look up.
+const MAX_DEFINITIONS: usize = 3;
+/// Call sites shown for each definition the diff changes.
+const MAX_CALLS: usize = 5;
+/// Lines of a definition shown, so long functions don't use up the budget.
+const MAX_DEFINITION_LINES: u64 = 40;
+/// Lines shown either side of a call site.
+const CALL_CONTEXT_LINES: u64 = 2;
+
+const RUST_QUERY: &str = "
+    (function_item name: (identifier) @name) @definition
+    (function_signature_item name: (identifier) @name) @definition
+    (struct_item name: (type_identifier) @name) @definition
+    (enum_item name: (type_identifier) @name) @definition
+    (trait_item name: (type_identifier) @name) @definition
+    (type_item name: (type_identifier) @name) @definition
+    (const_item name: (identifier) @name) @definition
+    (static_item name: (identifier) @name) @definition
+    (macro_definition name: (identifier) @name) @definition
+    (call_expression function: (identifier) @name) @call
+    (call_expression function: (scoped_identifier name: (identifier) @name)) @call
+    (call_expression function: (field_expression field: (field_identifier) @name)) @call
+    (macro_invocation macro: (identifier) @name) @call
+    (struct_expression name: (type_identifier) @name) @call
+";
+
+const TYPESCRIPT_QUERY: &str = "
+    (function_declaration name: (identifier) @name) @definition
+    (generator_function_declaration name: (identifier) @name) @definition
+    (class_declaration name: (type_identifier) @name) @definition
+    (abstract_class_declaration name: (type_identifier) @name) @definition
+    (method_definition name: (property_identifier) @name) @definition
+    (interface_declaration name: (type_identifier) @name) @definition
+    (type_alias_declaration name: (type_identifier) @name) @definition
+    (enum_declaration name: (identifier) @name) @definition
+    (variable_declarator name: (identifier) @name value: [(arrow_function) (function_expression)]) @definition
+    (call_expression function: (identifier) @name) @call
+    (call_expression function: (member_expression property: (property_identifier) @name)) @call
+    (new_expression constructor: (identifier) @name) @call
+";
+
+const PYTHON_QUERY: &str = "
+    (function_definition name: (identifier) @name) @definition
+    (class_definition name: (identifier) @name) @definition
+    (call function: (identifier) @name) @call
+    (call function: (attribute attribute: (identifier) @name)) @call
+";
+
+const GO_QUERY: &str = "
+    (function_declaration name: (identifier) @name) @definition
+    (method_declaration name: (field_identifier) @name) @definition
+    (type_spec name: (type_identifier) @name) @definition
+    (const_spec name: (identifier) @name) @definition
+    (call_expression function: (identifier) @name) @call
+    (call_expression function: (selector_expression field: (field_identifier) @name)) @call
+    (composite_literal type: (type_identifier) @name) @call
+";
+
+static QUERIES: LazyLock<HashMap<Language, Query>> = LazyLock::new(|| {
+    Language::ALL
+        .into_iter()
+        .map(|language| {
+            let query = Query::new(&language.get_grammar(), language.get_query_source())
+                .expect("Valid query");
+
+            (language, query)
+        })
+        .collect()
+});
+
+/// Languages the index can parse.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    Rust,
+    TypeScript,
+    /// TSX, which is also used for JavaScript as it's close enough to find symbols in.
+    Tsx,
+    Python,
+    Go,
+}
+
+impl Language {
+    const ALL: [Self; 5] = [
+        Self::Rust,
+        Self::TypeScript,
+        Self::Tsx,
+        Self::Python,
+        Self::Go,
+    ];
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+
+        match extension {
+            "rs" => Some(Self::Rust),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" | "js" | "jsx" | "mjs" | "cjs" => Some(Self::Tsx),
+            "py" => Some(Self::Python),
+            "go" => Some(Self::Go),
+            _ => None,
+        }
+    }
+
+    fn get_grammar(self) -> tree_sitter::Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    fn get_query_source(self) -> &'static str {
+        match self {
+            Self::Rust => RUST_QUERY,
+            Self::TypeScript | Self::Tsx => TYPESCRIPT_QUERY,
+            Self::Python => PYTHON_QUERY,
+            Self::Go => GO_QUERY,
+        }
+    }
+}
+
+/// Whether a file is in a language the index can parse and isn't a dependency or build output.
+pub fn is_indexable(path: &str) -> bool {
+    Language::from_path(path).is_some() && !path.split('/').any(|dir| IGNORED_DIRS.contains(&dir))
+}
+
+pub struct SourceFile {
+    pub path: String,
+    pub contents: String,
+}
+
+/// Where a symbol is defined or called.
+#[derive(Clone)]
+struct Location {
+    path: String,
+    /// First and last lines, counting from 1.
+    start_line: u64,
+    end_line: u64,
+}
+
+impl Location {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start_line <= end && start <= self.end_line
+    }
+}
+
+/// The definitions and call sites of the functions and types in a set of files, parsed with
+/// tree-sitter. Symbols are matched by name only, so it's a guide for what to show a model
+/// rather than an exact call graph.
+#[derive(Default)]
+pub struct CodeIndex {
+    files: HashMap<String, Vec<String>>,
+    definitions: HashMap<String, Vec<Location>>,
+    calls: HashMap<String, Vec<Location>>,
+}
+
+impl CodeIndex {
+    /// Parses the files, skipping any that aren't indexable or are too large.
+    pub fn new(files: impl IntoIterator<Item = SourceFile>) -> Self {
+        let mut index = Self::default();
+        let mut parser = Parser::new();
+
+        for file in files {
+            if file.contents.len() > MAX_FILE_SIZE || !is_indexable(&file.path) {
+                continue;
+            }
+
+            let Some(language) = Language::from_path(&file.path) else {
+                continue;
+            };
+
+            if let Err(err) = parser.set_language(&language.get_grammar()) {
+                tracing::warn!("Error loading grammar for {}: {err}", file.path);
+                continue;
+            }
+
+            let Some(tree) = parser.parse(&file.contents, None) else {
+                tracing::warn!("Error parsing {}, skipping", file.path);
+                continue;
+            };
+
+            let query = &QUERIES[&language];
+            let capture_names = query.capture_names();
+            let mut cursor = QueryCursor::new();
+            let mut matches = cursor.matches(query, tree.root_node(), file.contents.as_bytes());
+
+            while let Some(query_match) = matches.next() {
+                let mut name = None;
+                let mut symbol = None;
+
+                for capture in query_match.captures {
+                    match capture_names[capture.index as usize] {
+                        "name" => name = capture.node.utf8_text(file.contents.as_bytes()).ok(),
+                        kind => symbol = Some((kind, capture.node)),
+                    }
+                }
+
+                let (Some(name), Some((kind, node))) = (name, symbol) else {
+                    continue;
+                };
+
+                let location = Location {
+                    path: file.path.clone(),
+                    start_line: node.start_position().row as u64 + 1,
+                    end_line: node.end_position().row as u64 + 1,
+                };
+
+                let symbols = match kind {
+                    "definition" => &mut index.definitions,
+                    _ => &mut index.calls,
+                };
+
+                symbols.entry(name.to_string()).or_default().push(location);
+            }
+
+            index.files.insert(
+                file.path,
+                file.contents.lines().map(str::to_string).collect(),
+            );
+        }
+
+        tracing::info!(
+            "Indexed {} files with {} defined symbols",
+            index.files.len(),
+            index.definitions.len()
+        );
+
+        index
+    }
+
+    /// Finds code outside the diff that relates to the symbols it touches: the definitions of
+    /// functions and types called on added lines, then the call sites of definitions the diff
+    /// changes. The diff's files need to be in the index as they are after the change. Code the
+    /// diff already shows and secrets are left out. Returns `None` if nothing is found.
+    pub fn get_context(&self, pr_diff: &str, max_tokens: usize) -> Option<String> {
+        let changed: HashMap<&str, diff::ChangedLines> = diff::split_by_file(pr_diff)
+            .into_iter()
+            .map(|file| (file.path, diff::get_changed_lines(file.content)))
+            .collect();
+
+        let is_in_diff = |location: &Location| {
+            changed.get(location.path.as_str()).is_some_and(|lines| {
+                lines
+                    .hunks
+                    .iter()
+                    .any(|hunk| location.overlaps(*hunk.start(), *hunk.end()))
+            })
+        };
+
+        let is_added = |location: &Location| {
+            changed.get(location.path.as_str()).is_some_and(|lines| {
+                (location.start_line..=location.end_line).any(|l| lines.added.contains(&l))
+            })
+        };
+
+        let mut snippets = Vec::new();
+
+        for name in self.find_names(&self.calls, is_added) {
+            for definition in self.definitions[name].iter().filter(|d| !is_in_diff(d)) {
+                let end_line = definition
+                    .end_line
+                    .min(definition.start_line + MAX_DEFINITION_LINES - 1);
+
+                snippets.push((
+                    format!("Definition of `{name}`"),
+                    Location {
+                        end_line,
+                        ..definition.clone()
+                    },
+                    end_line < definition.end_line,
+                ));
+            }
+        }
+
+        for name in self.find_names(&self.definitions, is_added) {
+            let calls = self.calls.get(name).into_iter().flatten();
+
+            for call in calls.filter(|c| !is_in_diff(c)).take(MAX_CALLS) {
+                snippets.push((
+                    format!("Call of `{name}`"),
+                    Location {
+                        path: call.path.clone(),
+                        start_line: call.start_line.saturating_sub(CALL_CONTEXT_LINES).max(1),
+                        end_line: call.start_line + CALL_CONTEXT_LINES,
+                    },
+                    false,
+                ));
+            }
+        }
+
+        let mut context = String::new();
+        let mut seen = HashSet::new();
+
+        for (title, location, is_cut) in snippets {
+            if !seen.insert((location.path.clone(), location.start_line)) {
+                continue;
+            }
+
+            let Some(snippet) = self.format_snippet(&title, &location, is_cut) else {
+                continue;
+            };
+
+            if diff::estimate_tokens(&context) + diff::estimate_tokens(&snippet) > max_tokens {
+                break;
+            }
+
+            context.push_str(&snippet);
+        }
+
+        (!context.is_empty()).then_some(context)
+    }
+
+    /// Finds the names of the symbols at the given locations, leaving out ones too ambiguous to
+    /// look up.
+    fn find_names<'a>(
+        &'a self,
+        symbols: &'a HashMap<String, Vec<Location>>,
+        is_included: impl Fn(&Location) -> bool,
+    ) -> Vec<&'a str> {
+        let mut names: Vec<&str> = symbols
+            .iter()
+            .filter(|(name, locations)| {
+                locations.iter().any(&is_included)
+                    && self
+                        .definitions
+                        .get(name.as_str())
+                        .is_some_and(|d| d.len() <= MAX_DEFINITIONS)
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        // Keeps the context the same between runs, as map order isn't
+        names.sort_unstable();
+        names
+    }
+
+    fn format_snippet(&self, title: &str, location: &Location, is_cut: bool) -> Option<String> {
+        let lines = self.files.get(&location.path)?;
+        let end_line = location.end_line.min(lines.len() as u64);
+
+        let code = (location.start_line..=end_line)
+            .map(|n| format!("{n:>5}  {}\n", lines[n as usize - 1]))
+            .collect::<String>();
+
+        if code.is_empty() {
+            return None;
+        }
+
+        let code = redact::redact_file(&location.path, &code);
+        let cut = if is_cut { "  ...\n" } else { "" };
+
+        Some(format!("{title} in {}\n{code}{cut}\n", location.path))
+    }
+}
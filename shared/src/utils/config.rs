@@ -19,3 +19,12 @@ pub fn get_optional(var_name: &str) -> Option<String> {
         // Check if the value is an empty string and return None if so
         .and_then(|val| if val.is_empty() { None } else { Some(val) })
 }
+
+pub fn get_number(var_name: &str, default: usize) -> usize {
+    get_optional(var_name)
+        .map(|val| {
+            val.parse()
+                .unwrap_or_else(|_| panic!("{var_name} environment variable should be a number"))
+        })
+        .unwrap_or(default)
+}
@@ -0,0 +1,166 @@
+use std::{collections::HashSet, ops::RangeInclusive};
+
+pub(crate) const FILE_HEADER: &str = "diff --git ";
+
+pub struct FileDiff<'a> {
+    pub path: &'a str,
+    pub content: &'a str,
+}
+
+/// Splits a unified diff into the sections for each file it touches.
+pub fn split_by_file(diff: &str) -> Vec<FileDiff<'_>> {
+    if diff.is_empty() {
+        return Vec::new();
+    }
+
+    // Any content before the first file header is kept as its own section
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(
+            diff.match_indices(&format!("\n{FILE_HEADER}"))
+                .map(|(i, _)| i + 1),
+        )
+        .collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).map_or(diff.len(), |next| next - 1);
+            let content = &diff[start..end];
+
+            FileDiff {
+                path: get_path(content),
+                content,
+            }
+        })
+        .filter(|file| !file.content.trim().is_empty())
+        .collect()
+}
+
+/// Rough token count for prompt budgeting, assuming ~4 characters per token.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Cuts text down to roughly the given token count at the nearest line boundary, or mid-line if
+/// the first line alone is over the limit.
+pub fn truncate_to_tokens(text: &str, max_tokens: usize) -> &str {
+    let max_len = max_tokens * 4;
+
+    if text.len() <= max_len {
+        return text;
+    }
+
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let end = text[..end].rfind('\n').unwrap_or(end);
+
+    &text[..end]
+}
+
+/// Counts the added and removed lines in a diff, ignoring file headers.
+pub fn count_changed_lines(diff: &str) -> usize {
+    diff.lines()
+        .filter(|line| {
+            (line.starts_with('+') && !line.starts_with("+++"))
+                || (line.starts_with('-') && !line.starts_with("---"))
+        })
+        .count()
+}
+
+/// Finds the hunk of a file's diff covering the given line of the new version of the file.
+pub fn find_hunk<'a>(diff: &'a str, path: &str, line: u64) -> Option<&'a str> {
+    let file = split_by_file(diff).into_iter().find(|f| f.path == path)?;
+
+    let starts: Vec<usize> = file
+        .content
+        .match_indices("\n@@")
+        .map(|(i, _)| i + 1)
+        .collect();
+
+    starts.iter().enumerate().find_map(|(i, &start)| {
+        let end = starts
+            .get(i + 1)
+            .map_or(file.content.len(), |next| next - 1);
+        let hunk = &file.content[start..end];
+
+        let first_line = parse_hunk_new_start(hunk.lines().next()?)?;
+        let line_count = hunk
+            .lines()
+            .skip(1)
+            .filter(|l| !l.starts_with('-') && !l.starts_with('\\'))
+            .count() as u64;
+
+        (first_line..first_line + line_count.max(1))
+            .contains(&line)
+            .then_some(hunk)
+    })
+}
+
+/// The lines of the new version of a file that its diff touches.
+#[derive(Default)]
+pub struct ChangedLines {
+    /// Lines covered by each hunk, including its unchanged lines.
+    pub hunks: Vec<RangeInclusive<u64>>,
+    pub added: HashSet<u64>,
+}
+
+/// Finds the hunks and added lines in a file's diff, numbered as in the new version of the file.
+pub fn get_changed_lines(file_diff: &str) -> ChangedLines {
+    let mut changed = ChangedLines::default();
+    let mut new_line = None;
+
+    for line in file_diff.lines() {
+        if line.starts_with("@@") {
+            let start = parse_hunk_new_start(line).unwrap_or(0).max(1);
+
+            changed.hunks.push(start..=start);
+            new_line = Some(start);
+            continue;
+        }
+
+        let Some(current) = new_line else {
+            continue;
+        };
+
+        if line.starts_with('-') || line.starts_with('\\') {
+            continue;
+        }
+
+        if line.starts_with('+') {
+            changed.added.insert(current);
+        }
+
+        if let Some(hunk) = changed.hunks.last_mut() {
+            *hunk = *hunk.start()..=current;
+        }
+
+        new_line = Some(current + 1);
+    }
+
+    changed
+}
+
+/// Parses the start line of the new file from a hunk header like `@@ -10,7 +12,8 @@`.
+pub fn parse_hunk_new_start(hunk_header: &str) -> Option<u64> {
+    hunk_header
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix('+'))?
+        .split(',')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Gets the path of the new file from the start of a file's diff.
+pub(crate) fn get_path(file_diff: &str) -> &str {
+    let header = file_diff.lines().next().unwrap_or_default();
+
+    header
+        .rsplit_once(" b/")
+        .map(|(_, path)| path)
+        .unwrap_or(header)
+}
@@ -0,0 +1,77 @@
+use super::{diff, redact};
+use std::ops::RangeInclusive;
+
+/// Files that format to at most this many tokens are included whole.
+const MAX_WHOLE_FILE_TOKENS: usize = 4_000;
+/// Lines of surrounding code shown either side of each hunk when a file is too big to include
+/// whole.
+const CONTEXT_LINES: u64 = 30;
+
+/// Formats the new version of a changed file for a review prompt, so the model can see code
+/// defined outside the diff's hunks.
+///
+/// Each line is numbered and lines the diff added are marked with a `+`. Small files are
+/// included whole, larger ones as windows around each hunk, and the result is cut down to
+/// `max_tokens`. Secrets are redacted the same way as in diffs. Returns `None` if nothing fits.
+pub fn format_file(
+    path: &str,
+    contents: &str,
+    file_diff: &str,
+    max_tokens: usize,
+) -> Option<String> {
+    let contents = redact::redact_file(path, contents);
+    let lines: Vec<&str> = contents.lines().collect();
+    let changed = diff::get_changed_lines(file_diff);
+
+    let format_lines = |range: RangeInclusive<u64>| {
+        range
+            .filter_map(|n| {
+                let line = lines.get(n as usize - 1)?;
+                let marker = if changed.added.contains(&n) { '+' } else { ' ' };
+
+                Some(format!("{n:>5}{marker} {line}\n"))
+            })
+            .collect::<String>()
+    };
+
+    let header = format!("File: {path}\n");
+    let whole_file = format_lines(1..=lines.len() as u64);
+
+    let body = if diff::estimate_tokens(&whole_file) <= MAX_WHOLE_FILE_TOKENS {
+        whole_file
+    } else {
+        get_windows(&changed.hunks, lines.len() as u64)
+            .into_iter()
+            .map(format_lines)
+            .collect::<Vec<_>>()
+            .join("  ...\n")
+    };
+
+    let max_tokens = max_tokens.checked_sub(diff::estimate_tokens(&header))?;
+    let body = diff::truncate_to_tokens(&body, max_tokens);
+
+    (!body.trim().is_empty()).then(|| format!("{header}{body}"))
+}
+
+/// Widens each hunk by [`CONTEXT_LINES`] and merges the ones that overlap.
+fn get_windows(hunks: &[RangeInclusive<u64>], line_count: u64) -> Vec<RangeInclusive<u64>> {
+    let mut windows: Vec<RangeInclusive<u64>> = Vec::new();
+
+    for hunk in hunks {
+        let start = hunk.start().saturating_sub(CONTEXT_LINES).max(1);
+        let end = (hunk.end() + CONTEXT_LINES).min(line_count);
+
+        if start > end {
+            continue;
+        }
+
+        match windows.last_mut() {
+            Some(last) if start <= last.end() + 1 => {
+                *last = *last.start()..=end.max(*last.end());
+            }
+            _ => windows.push(start..=end),
+        }
+    }
+
+    windows
+}
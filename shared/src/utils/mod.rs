@@ -1,2 +1,7 @@
+pub mod code_index;
 pub mod config;
+pub mod diff;
 pub mod error;
+pub mod file_context;
+pub mod prompt;
+pub mod redact;